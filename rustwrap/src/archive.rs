@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use decompress::{decompress, ExtractOpts};
+use flate2::{write::GzEncoder, Compression};
+use fs_err as fs;
+use sha2::Digest;
+
+fn digest(file: &Path) -> Result<sha2::digest::Output<sha2::Sha256>> {
+    let mut file = fs::File::open(file)?;
+    let mut hasher = sha2::Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize())
+}
+
+/// Hex encoded SHA256 of a file on disk
+pub fn sha256(file: &Path) -> Result<String> {
+    let hash = digest(file)?;
+    Ok(format!("{hash:x}"))
+}
+
+/// SHA256 of a file on disk as a subresource integrity string (`sha256-<base64>`)
+pub fn sha256_sri(file: &Path) -> Result<String> {
+    Ok(format!("sha256-{}", base64::encode(digest(file)?)))
+}
+
+/// Hex encoded SHA256 of every file under `dir`, by `/` separated path relative to `dir`
+pub fn sha256_tree(dir: &Path) -> Result<BTreeMap<String, String>> {
+    fn walk(root: &Path, dir: &Path, out: &mut BTreeMap<String, String>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(root, &path, out)?;
+            } else {
+                let rel = path
+                    .strip_prefix(root)?
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                out.insert(rel, sha256(&path)?);
+            }
+        }
+        Ok(())
+    }
+    let mut out = BTreeMap::new();
+    walk(dir, dir, &mut out)?;
+    Ok(out)
+}
+
+/// Magic bytes of the archive formats `decompress` reads, and the extensions it knows them by
+const FORMATS: &[(&[u8], &[&str])] = &[
+    (&[0x1f, 0x8b], &[".tar.gz", ".tgz"]),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0], &[".tar.xz", ".txz"]),
+    (b"PK\x03\x04", &[".zip"]),
+];
+
+/// `file_name`, with the extension `decompress` needs to read the archive in `file`,
+/// as told by its content. `None` when `file` is not an archive it reads.
+pub fn archive_name(file: &Path, file_name: &str) -> Result<Option<String>> {
+    let mut magic = Vec::with_capacity(8);
+    io::Read::take(fs::File::open(file)?, 8).read_to_end(&mut magic)?;
+    let Some((_, exts)) = FORMATS.iter().find(|(m, _)| magic.starts_with(m)) else {
+        return Ok(None);
+    };
+    let lower = file_name.to_lowercase();
+    Ok(Some(if exts.iter().any(|ext| lower.ends_with(ext)) {
+        file_name.to_string()
+    } else {
+        format!("{file_name}{}", exts[0])
+    }))
+}
+
+fn single_dir(dir: &Path) -> Result<Option<String>> {
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => {
+            Ok(Some(entry.file_name().to_string_lossy().to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Name of the single top-level folder an archive wraps its content in, if any.
+/// Release archives typically look like `tool-x86_64-linux/tool`.
+pub fn root_dir(archive: &Path) -> Result<Option<String>> {
+    let scratch = tempfile::tempdir()?;
+    decompress(archive, scratch.path(), &ExtractOpts { strip: 0 })?;
+    single_dir(scratch.path())
+}
+
+/// Read the content of a binary out of a release archive, looking at the archive
+/// root and inside its top-level folder.
+pub fn read_bin(archive: &Path, bin_name: &str) -> Result<Vec<u8>> {
+    let scratch = tempfile::tempdir()?;
+    decompress(archive, scratch.path(), &ExtractOpts { strip: 0 })?;
+    let bin_path = match single_dir(scratch.path())? {
+        Some(dir) => scratch.path().join(dir).join(bin_name),
+        None => scratch.path().join(bin_name),
+    };
+    fs::read(&bin_path).with_context(|| format!("'{bin_name}' not found in {archive:?}"))
+}
+
+/// A file to pack into a tarball
+pub struct Entry<'a> {
+    pub path: String,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+/// Build a reproducible tarball (root owned, zero mtime).
+/// Parent folders of every entry are added ahead of it.
+pub fn tar(entries: &[Entry<'_>]) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut dirs = BTreeSet::new();
+    for entry in entries {
+        let parts = entry.path.split('/').collect::<Vec<_>>();
+        for i in 0..parts.len() - 1 {
+            let dir = format!("{}/", parts[..=i].join("/"));
+            if dirs.insert(dir.clone()) {
+                let mut header = header(tar::EntryType::Directory, 0o755, 0);
+                builder.append_data(&mut header, &dir, io::empty())?;
+            }
+        }
+        let mut header = header(tar::EntryType::Regular, entry.mode, entry.data.len());
+        builder.append_data(&mut header, &entry.path, entry.data)?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+    gz.write_all(data)?;
+    Ok(gz.finish()?)
+}
+
+/// Build a reproducible gzipped tarball, see [`tar`]
+pub fn tar_gz(entries: &[Entry<'_>]) -> Result<Vec<u8>> {
+    gzip(&tar(entries)?)
+}
+
+/// Build a reproducible zip file (zip epoch mtime), entries are written in order
+pub fn zip(entries: &[Entry<'_>]) -> Result<Vec<u8>> {
+    let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    for entry in entries {
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip::DateTime::default())
+            .unix_permissions(entry.mode);
+        zip.start_file(entry.path.as_str(), options)?;
+        zip.write_all(entry.data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+fn header(kind: tar::EntryType, mode: u32, size: usize) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_size(size as u64);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header
+}
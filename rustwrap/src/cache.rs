@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::archive;
+
+/// What was downloaded from a url, and how to revalidate it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub url: String,
+    pub file_name: String,
+    pub sha256: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Downloads kept across runs: files by content in `blobs/<sha256>/<file name>`,
+/// and the last response for every url in `urls/`
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+fn url_key(url: &str) -> String {
+    format!("{:x}", sha2::Sha256::digest(url.as_bytes()))
+}
+
+/// Write through a temp file so concurrent readers never see half a file
+fn write_atomic(path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let tmp = path.with_file_name(format!(
+        ".{}.{}-{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    ));
+    write(&tmp)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `rustwrap` in the user's cache folder: `$XDG_CACHE_HOME` or `~/.cache` on Linux,
+    /// `~/Library/Caches` on macOS and `%LOCALAPPDATA%` on Windows
+    pub fn default_dir() -> Option<PathBuf> {
+        let env = |name| {
+            std::env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+        let dir = if cfg!(windows) {
+            env("LOCALAPPDATA")
+        } else if cfg!(target_os = "macos") {
+            env("HOME").map(|home| home.join("Library/Caches"))
+        } else {
+            env("XDG_CACHE_HOME").or_else(|| env("HOME").map(|home| home.join(".cache")))
+        };
+        dir.map(|d| d.join("rustwrap"))
+    }
+
+    /// A cached file with content `sha256`. A file that no longer hashes to it (truncated
+    /// or corrupted on disk) is evicted instead.
+    pub fn blob(&self, sha256: &str) -> Option<PathBuf> {
+        let sha256 = sha256.to_lowercase();
+        let dir = self.dir.join("blobs").join(&sha256);
+        let blob = fs::read_dir(&dir)
+            .ok()?
+            .filter_map(std::result::Result::ok)
+            .find(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| e.path())?;
+        if archive::sha256(&blob).ok()? != sha256 {
+            tracing::warn!(blob = %blob.display(), "evicting a corrupted cache blob");
+            let _ = fs::remove_dir_all(&dir);
+            return None;
+        }
+        Some(blob)
+    }
+
+    /// The last response stored for `url`, when its file is still cached
+    pub fn entry(&self, url: &str) -> Option<(Entry, PathBuf)> {
+        let content = fs::read_to_string(self.dir.join("urls").join(url_key(url))).ok()?;
+        let entry: Entry = serde_json::from_str(&content).ok()?;
+        let blob = self.blob(&entry.sha256)?;
+        Some((entry, blob))
+    }
+
+    /// Keep `file` downloaded from `url`, returns its cache entry
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO failed
+    pub fn store(
+        &self,
+        url: &str,
+        file: &Path,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Result<Entry> {
+        let sha256 = archive::sha256(file)?;
+        let file_name = file
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if self.blob(&sha256).is_none() {
+            let blob_dir = self.dir.join("blobs").join(&sha256);
+            fs::create_dir_all(&blob_dir)?;
+            write_atomic(&blob_dir.join(&file_name), |tmp| {
+                fs::copy(file, tmp)?;
+                Ok(())
+            })?;
+        }
+
+        let entry = Entry {
+            url: url.to_string(),
+            file_name,
+            sha256,
+            etag,
+            last_modified,
+        };
+        let urls_dir = self.dir.join("urls");
+        fs::create_dir_all(&urls_dir)?;
+        write_atomic(&urls_dir.join(url_key(url)), |tmp| {
+            fs::write(tmp, serde_json::to_string_pretty(&entry)?)?;
+            Ok(())
+        })?;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_corrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().join("cache"));
+        let file = dir.path().join("recon.tar.gz");
+        fs::write(&file, "recon").unwrap();
+        let entry = cache
+            .store("https://example.com/recon.tar.gz", &file, None, None)
+            .unwrap();
+        let blob = cache.blob(&entry.sha256).unwrap();
+        assert_eq!(fs::read_to_string(&blob).unwrap(), "recon");
+
+        // truncated on disk, it is not served and not kept around
+        fs::write(&blob, "rec").unwrap();
+        assert!(cache.blob(&entry.sha256).is_none());
+        assert!(!blob.exists());
+        assert!(cache.entry("https://example.com/recon.tar.gz").is_none());
+    }
+}
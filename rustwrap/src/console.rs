@@ -8,7 +8,10 @@ pub static INFO: Emoji<'_, '_> = Emoji("🍭", "*");
 pub static DOWNLOAD: Emoji<'_, '_> = Emoji("⚡️", "!");
 pub static PKG: Emoji<'_, '_> = Emoji("📦", "*");
 pub static COFFEE: Emoji<'_, '_> = Emoji("☕️", "*");
+pub static PLAN: Emoji<'_, '_> = Emoji("📋", "*");
+/// Output for user facing progress messages
 pub trait Console {
+    /// Print a line
     fn say(&mut self, text: &str);
 }
 
@@ -20,6 +23,7 @@ impl Console for EnvConsole {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Default)]
 pub struct MemConsole {
     pub buffer: Vec<String>,
@@ -1,26 +1,44 @@
 #![allow(clippy::use_self)]
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use fs_err as fs;
 use serde::{Deserialize, Serialize};
 use serde_variant::to_variant_name;
-use std::{borrow::Cow, fmt::Display, path::Path};
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display, path::Path};
 
-use crate::{
-    console::Console,
-    providers::{brew::BrewOpts, npm::NpmOpts},
-};
+use crate::console::Console;
+use crate::infer;
 
+/// Operating system of a target, named as node's `process.platform`
 #[derive(Default, Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub enum Platform {
+    /// Unknown
     #[default]
     #[serde(rename = "unknown")]
     Unknown,
+    /// Linux
     #[serde(rename = "linux")]
     Linux,
+    /// Windows
     #[serde(rename = "win32")]
     Win32,
+    /// macOS
     #[serde(rename = "darwin")]
     Darwin,
+    /// FreeBSD
+    #[serde(rename = "freebsd")]
+    FreeBSD,
+    /// NetBSD
+    #[serde(rename = "netbsd")]
+    NetBSD,
+    /// OpenBSD
+    #[serde(rename = "openbsd")]
+    OpenBSD,
+    /// illumos and Solaris
+    #[serde(rename = "sunos", alias = "illumos")]
+    SunOS,
+    /// Android
+    #[serde(rename = "android")]
+    Android,
 }
 
 impl Display for Platform {
@@ -30,13 +48,34 @@ impl Display for Platform {
     }
 }
 
+/// CPU architecture of a target, named as node's `process.arch`
 #[derive(Default, Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
 pub enum Architecture {
+    /// x86-64
     #[default]
     #[serde(rename = "x64")]
     X64,
+    /// aarch64
     #[serde(rename = "arm64")]
     ARM64,
+    /// 32-bit x86 (i686)
+    #[serde(rename = "ia32")]
+    IA32,
+    /// 32-bit arm (armv7)
+    #[serde(rename = "arm")]
+    ARM,
+    /// riscv64
+    #[serde(rename = "riscv64")]
+    RISCV64,
+    /// IBM Z
+    #[serde(rename = "s390x")]
+    S390X,
+    /// 64-bit little endian powerpc
+    #[serde(rename = "ppc64")]
+    PPC64,
+    /// loongarch64
+    #[serde(rename = "loong64")]
+    LOONG64,
 }
 
 impl Display for Architecture {
@@ -46,50 +85,204 @@ impl Display for Architecture {
     }
 }
 
+/// C library a Linux target links against
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub enum Libc {
+    /// glibc
+    #[serde(rename = "gnu")]
+    Gnu,
+    /// musl
+    #[serde(rename = "musl")]
+    Musl,
+}
+
+impl Display for Libc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(to_variant_name(self).unwrap())?;
+        Ok(())
+    }
+}
+
+/// A release archive for one platform and architecture
 #[derive(Default, Deserialize, Serialize, Clone, Debug)]
 pub struct Target {
+    /// Rust target triple (`aarch64-unknown-linux-musl`), sets `platform`, `arch` and `libc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub triple: Option<String>,
+    /// Operating system
+    #[serde(default)]
     pub platform: Platform,
+    /// CPU architecture
+    #[serde(default)]
     pub arch: Architecture,
+    /// C library, for Linux targets that ship a build per libc
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub libc: Option<Libc>,
+    /// Download url, `__VERSION__` is replaced with the version being wrapped.
+    /// Also where packages point to, so keep it with `path` when publishing a package
+    /// that downloads from it (brew, scoop, ...)
+    #[serde(default)]
     pub url_template: String,
+    /// Local file or glob (`__VERSION__` is replaced too) used instead of downloading
+    /// `url_template`, matching a single release archive or a raw binary to pack
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Binary name, when it differs from the package name
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub bin_name: Option<String>,
+    /// Expected sha256 of the archive, checked after download
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Url of a checksums file (`<sha256>  <file name>` per line, as `sha256sum` writes it)
+    /// listing the archive, `__VERSION__` is replaced with the version being wrapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksums_url: Option<String>,
+    /// Path of the archive on disk, once downloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub archive: Option<String>,
 }
 
 impl Target {
+    /// `<platform>-<arch>`, e.g. `linux-x64`, with a `-<libc>` suffix when one is set
     pub fn tuple_slug(&self) -> String {
-        format!(
+        let slug = format!(
             "{}-{}",
             to_variant_name(&self.platform).unwrap(),
             to_variant_name(&self.arch).unwrap()
-        )
+        );
+        match &self.libc {
+            Some(libc) => format!("{slug}-{libc}"),
+            None => slug,
+        }
     }
+    /// File name of binary `name` on this target's platform
     pub fn bin_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
         match self.platform {
             Platform::Win32 => format!("{name}.exe").into(),
             _ => name.into(),
         }
     }
+    /// libc the target links against, glibc unless set
+    pub fn libc_or_gnu(&self) -> Libc {
+        self.libc.clone().unwrap_or(Libc::Gnu)
+    }
+    /// Download url for `version`
     pub fn url(&self, version: &str) -> String {
         self.url_template.replace("__VERSION__", version)
     }
 }
 
+/// One target per platform and architecture, for packages that cannot tell libcs apart.
+/// When a release ships builds for more than one libc, the `prefer` build is kept.
+pub fn one_per_arch<'a>(
+    targets: impl IntoIterator<Item = &'a Target>,
+    prefer: Libc,
+) -> Vec<&'a Target> {
+    let mut picked: Vec<&Target> = vec![];
+    for target in targets {
+        let same = picked
+            .iter()
+            .position(|t| t.platform == target.platform && t.arch == target.arch);
+        match same {
+            Some(i) if picked[i].libc_or_gnu() != prefer && target.libc_or_gnu() == prefer => {
+                picked[i] = target;
+            }
+            Some(_) => {}
+            None => picked.push(target),
+        }
+    }
+    picked
+}
+
+/// A `rustwrap.yaml` configuration
 #[derive(Deserialize, Default)]
 pub struct Config {
+    /// GitHub repo (`owner/name`) used to discover the latest release
     pub repo: Option<String>,
+    /// Release archives to wrap
+    #[serde(default)]
     pub targets: Vec<Target>,
-    pub npm: Option<NpmOpts>,
-    pub brew: Option<BrewOpts>,
+    /// Add a target for every asset of the `repo` release that names a platform and
+    /// architecture, next to `targets`
+    #[serde(default)]
+    pub auto_targets: bool,
+    /// Provider sections (`npm`, `brew`, ...) in file order, see [`crate::Registry`]
+    #[serde(flatten)]
+    pub providers: serde_yaml::Mapping,
 }
 
 impl Config {
+    /// Load a configuration from a YAML file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or parsed,
+    /// or a target has neither a platform nor a known triple
     pub fn load<P: AsRef<Path>>(file: P) -> Result<Self> {
-        let r: Config = serde_yaml::from_reader(fs::File::open(file.as_ref())?)?;
+        let mut r: Config = serde_yaml::from_reader(fs::File::open(file.as_ref())?)?;
+        for target in &mut r.targets {
+            if let Some(triple) = &target.triple {
+                let (platform, arch, libc) = infer::triple(triple)
+                    .ok_or_else(|| anyhow::anyhow!("unsupported target triple '{triple}'"))?;
+                target.platform = platform;
+                target.arch = arch;
+                target.libc = target.libc.take().or(libc);
+            } else if target.platform == Platform::Unknown {
+                bail!(
+                    "target '{}' needs a `platform` and `arch`, or a `triple`",
+                    target.url_template
+                );
+            }
+        }
         Ok(r)
     }
 }
 
+pub const RELEASE_MANIFEST: &str = "targets.json";
+
+/// Targets downloaded for a version, saved by the download stage for the later ones
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Release {
+    /// Version the targets were downloaded for
+    pub version: String,
+    /// Targets, with their downloaded archive paths
+    pub targets: Vec<Target>,
+    /// sha256 of every target archive, by archive path
+    pub checksums: BTreeMap<String, String>,
+}
+
+impl Release {
+    /// Load the manifest the download stage left in `releases_dir`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if nothing was downloaded yet
+    pub fn load(releases_dir: &Path) -> Result<Self> {
+        let file = releases_dir.join(RELEASE_MANIFEST);
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("cannot read {file:?}, run `rustwrap download` first"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Save the manifest into `releases_dir`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an IO failed
+    pub fn save(&self, releases_dir: &Path) -> Result<()> {
+        fs::create_dir_all(releases_dir)?;
+        fs::write(
+            releases_dir.join(RELEASE_MANIFEST),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// State shared with providers during a run
 pub struct Session<'a> {
+    /// The loaded configuration
     pub config: &'a Config,
+    /// Where progress is reported
     pub console: &'a mut dyn Console,
 }
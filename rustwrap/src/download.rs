@@ -1,20 +1,85 @@
 use crate::console::{style, DOWNLOAD, FINGER};
 use anyhow::{bail, Context, Result};
 use fs_err as fs;
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::header;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::{blocking::Client, header, StatusCode};
+use sha2::Digest;
+use std::collections::HashMap;
 use std::io::{self, Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use crate::data::{Session, Target};
+use crate::archive;
+use crate::cache::Cache;
+use crate::data::{Platform, Session, Target};
 
 pub const DEFAULT_PROGRESS_TEMPLATE: &str = "   {prefix:} {bar:.green/red} {msg}";
+/// Targets downloaded at the same time
+const PARALLEL_DOWNLOADS: usize = 4;
+/// Attempts per target, retries wait twice as long as the one before
+const MAX_ATTEMPTS: u32 = 4;
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A non-success HTTP status, kept apart from other errors to decide on retrying
+#[derive(Debug)]
+struct StatusError {
+    url: String,
+    status: StatusCode,
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Downloading '{}' failed with status: {:?}",
+            self.url, self.status
+        )
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// Whether trying again may help: anything but a client error, except timeouts and rate limits
+fn retryable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<StatusError>().is_none_or(|e| {
+        !e.status.is_client_error()
+            || e.status == StatusCode::REQUEST_TIMEOUT
+            || e.status == StatusCode::TOO_MANY_REQUESTS
+    })
+}
+
+/// An HTTP client to share between downloads
+pub fn client() -> Client {
+    #[cfg(target_os = "linux")]
+    {
+        if ::std::env::var_os("SSL_CERT_FILE").is_none() {
+            ::std::env::set_var("SSL_CERT_FILE", "/etc/ssl/certs/ca-certificates.crt");
+        }
+        if ::std::env::var_os("SSL_CERT_DIR").is_none() {
+            ::std::env::set_var("SSL_CERT_DIR", "/etc/ssl/certs");
+        }
+    }
+    Client::builder()
+        .user_agent("rust-reqwest/rustwrap")
+        .build()
+        .expect("cannot build http client")
+}
 
 #[derive(Debug)]
 pub struct Download {
     show_progress: bool,
     url: String,
     headers: reqwest::header::HeaderMap,
+    client: Option<Client>,
+    multi_progress: Option<MultiProgress>,
+    cache: Option<Cache>,
+    expected_sha256: Option<String>,
+    fallback_name: String,
+    /// File names taken in the output folder, by the url they were downloaded from
+    names: Option<Arc<Mutex<HashMap<String, String>>>>,
 }
 
 #[allow(dead_code)]
@@ -25,6 +90,12 @@ impl Download {
             show_progress: false,
             url: url.to_owned(),
             headers: reqwest::header::HeaderMap::new(),
+            client: None,
+            multi_progress: None,
+            cache: None,
+            expected_sha256: None,
+            fallback_name: "download".to_string(),
+            names: None,
         }
     }
 
@@ -33,6 +104,69 @@ impl Download {
         self
     }
 
+    /// Use a shared client instead of building one for this download
+    pub fn set_client(&mut self, client: Client) -> &mut Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Draw progress as part of `multi_progress`, for concurrent downloads
+    pub fn set_multi_progress(&mut self, multi_progress: MultiProgress) -> &mut Self {
+        self.multi_progress = Some(multi_progress);
+        self
+    }
+
+    /// Reuse and keep downloads in `cache`
+    pub fn set_cache(&mut self, cache: Option<Cache>) -> &mut Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Content expected at the url, a cached file with it is used without asking the server
+    pub fn set_expected_sha256(&mut self, sha256: Option<String>) -> &mut Self {
+        self.expected_sha256 = sha256;
+        self
+    }
+
+    /// File name to use when neither the server nor the url give one
+    pub fn set_fallback_name(&mut self, name: &str) -> &mut Self {
+        self.fallback_name = name.to_string();
+        self
+    }
+
+    /// Share the file names taken in the output folder with other downloads into it, a
+    /// name another url already took is prefixed with the fallback name
+    pub fn set_names(&mut self, names: Arc<Mutex<HashMap<String, String>>>) -> &mut Self {
+        self.names = Some(names);
+        self
+    }
+
+    /// `file_name`, or `<fallback name>-<file_name>` when another url took it
+    fn claim_name(&self, file_name: String) -> String {
+        let Some(names) = &self.names else {
+            return file_name;
+        };
+        let mut names = names.lock().expect("poisoned");
+        let file_name = match names.get(&file_name) {
+            Some(url) if *url != self.url => format!("{}-{file_name}", self.fallback_name),
+            _ => file_name,
+        };
+        names.insert(file_name.clone(), self.url.clone());
+        file_name
+    }
+
+    /// Copy a cached file into `out_dir`, under its original name
+    fn copy_cached(&self, blob: &Path, out_dir: &Path) -> Result<String> {
+        let file_name = blob
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let out_file = out_dir.join(self.claim_name(file_name));
+        fs::copy(blob, &out_file)?;
+        Ok(out_file.to_string_lossy().to_string())
+    }
+
     pub fn set_headers(&mut self, headers: reqwest::header::HeaderMap) -> &mut Self {
         self.headers = headers;
         self
@@ -59,29 +193,131 @@ impl Download {
         Ok(capture.as_str())
     }
 
+    fn send(&self, headers: reqwest::header::HeaderMap) -> Result<reqwest::blocking::Response> {
+        let resp = self
+            .client
+            .clone()
+            .unwrap_or_else(client)
+            .get(&self.url)
+            .headers(headers)
+            .send()
+            .with_context(|| format!("downloading {}", &self.url))?;
+        if !resp.status().is_success() && resp.status() != StatusCode::NOT_MODIFIED {
+            return Err(StatusError {
+                url: self.url.clone(),
+                status: resp.status(),
+            }
+            .into());
+        }
+        Ok(resp)
+    }
+
+    /// Download into memory, for small text files
+    pub fn read_to_string(&self) -> Result<String> {
+        Ok(self.send(self.headers.clone())?.text()?)
+    }
+
+    /// Where an unfinished download of this url is kept, so a retry can resume it
+    fn partial_file(&self, out_dir: &Path) -> std::path::PathBuf {
+        let hash = format!("{:x}", sha2::Sha256::digest(self.url.as_bytes()));
+        out_dir.join(format!(".{}.part", &hash[..16]))
+    }
+
+    /// Where the `ETag` or `Last-Modified` of the object a partial file holds the start of
+    /// is kept, to resume only from that same object
+    fn validator_file(&self, out_dir: &Path) -> std::path::PathBuf {
+        self.partial_file(out_dir).with_extension("validator")
+    }
+
+    /// Throw away an unfinished download, to start over
+    fn discard_partial(&self, out_dir: &Path) -> Result<()> {
+        for file in [self.partial_file(out_dir), self.validator_file(out_dir)] {
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Download into `out_dir`, named as the server suggests. An unfinished download
+    /// left by an earlier failed attempt is resumed with a `Range` request, guarded by
+    /// `If-Range` so a changed object is downloaded again from the start.
+    ///
+    /// With a cache, a file with the expected sha256 is used as is, and a file cached for
+    /// the url is used when the server says it did not change (`ETag`/`Last-Modified`).
     pub fn download_to(&self, out_dir: &Path) -> Result<String> {
+        if !out_dir.exists() {
+            fs::create_dir_all(out_dir)?;
+        }
+        if let Some(blob) = self.cache.as_ref().and_then(|cache| {
+            self.expected_sha256
+                .as_deref()
+                .and_then(|sha| cache.blob(sha))
+        }) {
+            return self.copy_cached(&blob, out_dir);
+        }
+
+        let partial_file = self.partial_file(out_dir);
+        let validator_file = self.validator_file(out_dir);
+        // without a validator there is no telling what the partial file is part of
+        let if_range = fs::read_to_string(&validator_file)
+            .ok()
+            .and_then(|v| header::HeaderValue::from_str(v.trim()).ok());
+        let resume_from = match if_range {
+            Some(_) => fs::metadata(&partial_file).map_or(0, |m| m.len()),
+            None => 0,
+        };
+
         let mut headers = self.headers.clone();
-        if !headers.contains_key(header::USER_AGENT) {
+        let cached = self.cache.as_ref().and_then(|cache| cache.entry(&self.url));
+        if let (true, Some(if_range)) = (resume_from > 0, if_range) {
             headers.insert(
-                header::USER_AGENT,
-                "rust-reqwest/rustwrap".parse().expect("invalid user-agent"),
+                header::RANGE,
+                format!("bytes={resume_from}-")
+                    .parse()
+                    .expect("invalid range"),
             );
+            headers.insert(header::IF_RANGE, if_range);
+        } else if let Some((entry, _)) = &cached {
+            if let Some(etag) = entry.etag.as_deref().and_then(|v| v.parse().ok()) {
+                headers.insert(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = entry.last_modified.as_deref().and_then(|v| v.parse().ok()) {
+                headers.insert(header::IF_MODIFIED_SINCE, modified);
+            }
         }
-
-        #[cfg(target_os = "linux")]
-        {
-            if ::std::env::var_os("SSL_CERT_FILE").is_none() {
-                ::std::env::set_var("SSL_CERT_FILE", "/etc/ssl/certs/ca-certificates.crt");
+        let mut resp = match self.send(headers) {
+            Err(err)
+                if err
+                    .downcast_ref::<StatusError>()
+                    .is_some_and(|e| e.status == StatusCode::RANGE_NOT_SATISFIABLE) =>
+            {
+                // the partial file is not a prefix of what is served now, start over
+                self.discard_partial(out_dir)?;
+                return self.download_to(out_dir);
             }
-            if ::std::env::var_os("SSL_CERT_DIR").is_none() {
-                ::std::env::set_var("SSL_CERT_DIR", "/etc/ssl/certs");
+            res => res?,
+        };
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let Some((_, blob)) = &cached {
+                return self.copy_cached(blob, out_dir);
             }
+            bail!("'{}' answered not modified to a plain request", self.url);
+        }
+        let validator = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string)
+        };
+        let (etag, last_modified) = (validator(header::ETAG), validator(header::LAST_MODIFIED));
+        // servers that ignore `Range`, or whose object changed, send everything again
+        let resumed = resp.status() == StatusCode::PARTIAL_CONTENT;
+        if resumed && content_range_start(&resp) != Some(resume_from) {
+            drop(resp);
+            self.discard_partial(out_dir)?;
+            return self.download_to(out_dir);
         }
-        let mut resp = reqwest::blocking::Client::new()
-            .get(&self.url)
-            .headers(headers)
-            .send()
-            .with_context(|| format!("downloading {}", &self.url))?;
         let size = resp
             .headers()
             .get(reqwest::header::CONTENT_LENGTH)
@@ -90,30 +326,34 @@ impl Download {
                     .map(|s| s.parse::<u64>().unwrap_or(0))
                     .unwrap_or(0)
             });
-        if !resp.status().is_success() {
-            bail!(
-                "Downloading '{}' failed with status: {:?}",
-                &self.url,
-                resp.status()
-            )
-        }
         let show_progress = if size == 0 { false } else { self.show_progress };
 
+        // the server's name, or the last part of the url it was finally served from
         let file_name = resp
             .headers()
             .get(reqwest::header::CONTENT_DISPOSITION)
             .and_then(|value| Self::filename_from_content_disposition(value).ok())
-            .unwrap_or("temp.bin")
-            .to_string();
+            .or_else(|| resp.url().path_segments()?.next_back())
+            .filter(|name| !name.is_empty())
+            .map_or_else(|| self.fallback_name.clone(), ToString::to_string);
 
-        if !out_dir.exists() {
-            fs::create_dir_all(out_dir)?;
-        }
-        let out_file = out_dir.join(&file_name);
-        let mut dest_file = fs::File::create(&out_file).unwrap();
+        let mut dest_file = if resumed {
+            fs::OpenOptions::new().append(true).open(&partial_file)?
+        } else {
+            // weak etags cannot be used with `If-Range`
+            match etag
+                .as_deref()
+                .filter(|e| !e.starts_with("W/"))
+                .or(last_modified.as_deref())
+            {
+                Some(v) => fs::write(&validator_file, v)?,
+                None => self.discard_partial(out_dir)?,
+            }
+            fs::File::create(&partial_file)?
+        };
 
         let bar = if show_progress {
-            let pb = ProgressBar::new(size);
+            let pb = ProgressBar::new(if resumed { resume_from + size } else { size });
             pb.set_prefix(FINGER.to_string());
             pb.set_style(
                 ProgressStyle::default_bar()
@@ -121,19 +361,48 @@ impl Download {
                     .expect("set ProgressStyle template failed")
                     .progress_chars("━  ·"), // ━━╸━━━━━━━━━━━━━
             );
-            pb
+            if resumed {
+                pb.set_position(resume_from);
+            }
+            match &self.multi_progress {
+                Some(multi_progress) => multi_progress.add(pb),
+                None => pb,
+            }
         } else {
             ProgressBar::hidden()
         };
 
         bar.set_message(style(&file_name).dim().to_string());
         copy_with_progress(&bar, &mut resp, &mut dest_file)?;
+        drop(dest_file);
 
+        let file_name =
+            self.claim_name(archive::archive_name(&partial_file, &file_name)?.unwrap_or(file_name));
+        let out_file = out_dir.join(&file_name);
+        fs::rename(&partial_file, &out_file)?;
+        self.discard_partial(out_dir)?;
         bar.finish_with_message(file_name);
+        if let Some(cache) = &self.cache {
+            cache.store(&self.url, &out_file, etag, last_modified)?;
+        }
         Ok(out_file.to_string_lossy().to_string())
     }
 }
 
+/// First byte of a `206` response, from `Content-Range: bytes <first>-<last>/<size>`
+fn content_range_start(resp: &reqwest::blocking::Response) -> Option<u64> {
+    resp.headers()
+        .get(header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
 pub fn copy_with_progress<R: ?Sized + Read, W: ?Sized + Write>(
     progress: &ProgressBar,
     reader: &mut R,
@@ -157,6 +426,9 @@ pub struct TargetsDownloader<'a> {
     targets: &'a [Target],
     out_dir: &'a Path,
     show_progress: bool,
+    client: Client,
+    cache: Option<Cache>,
+    names: Arc<Mutex<HashMap<String, String>>>,
 }
 
 impl<'a> TargetsDownloader<'a> {
@@ -165,8 +437,51 @@ impl<'a> TargetsDownloader<'a> {
             targets,
             out_dir,
             show_progress: true,
+            client: client(),
+            cache: Cache::default_dir().map(Cache::new),
+            names: Arc::default(),
+        }
+    }
+
+    /// Download through `cache`, or always from the network when `None`
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Download `url`, retrying with backoff and resuming what earlier attempts got
+    fn download_with_retry(
+        &self,
+        target: &Target,
+        version: &str,
+        multi_progress: &MultiProgress,
+    ) -> Result<String> {
+        let mut d = Download::from_url(&target.url(version));
+        d.show_progress(self.show_progress)
+            .set_client(self.client.clone())
+            .set_multi_progress(multi_progress.clone())
+            .set_cache(self.cache.clone())
+            .set_expected_sha256(target.sha256.clone())
+            .set_fallback_name(&target.tuple_slug())
+            .set_names(self.names.clone());
+        let mut backoff = FIRST_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match d.download_to(self.out_dir) {
+                Err(err) if attempt < MAX_ATTEMPTS && retryable(&err) => {
+                    multi_progress.println(format!(
+                        "   {FINGER} {err:#}, retrying in {}s ({attempt}/{MAX_ATTEMPTS})",
+                        backoff.as_secs()
+                    ))?;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                res => return res,
+            }
         }
     }
+
     pub fn download(&self, session: &mut Session<'_>, version: &str) -> Result<Vec<Target>> {
         session.console.say(&format!(
             "{} downloading {} target release(s) into {}",
@@ -174,24 +489,524 @@ impl<'a> TargetsDownloader<'a> {
             self.targets.len(),
             style(&self.out_dir.to_string_lossy()).magenta(),
         ));
-        self.targets
-            .iter()
-            .map(|t| {
-                // if we have an archive and it exists on disk return it, otherwise download it
-                if t.archive.is_some() && t.archive.as_ref().is_some_and(|a| Path::new(&a).exists())
-                {
-                    Ok(t.clone())
+        let multi_progress = if self.show_progress {
+            MultiProgress::new()
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        };
+
+        // workers pick the next target off a shared index, results keep target order
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(
+            (0..self.targets.len())
+                .map(|_| None)
+                .collect::<Vec<Option<Result<Target>>>>(),
+        );
+        thread::scope(|scope| {
+            for _ in 0..PARALLEL_DOWNLOADS.min(self.targets.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(t) = self.targets.get(i) else {
+                        break;
+                    };
+                    // if we have an archive and it exists on disk return it, otherwise take it
+                    // from a local path or download it
+                    let res = if t.archive.as_ref().is_some_and(|a| Path::new(&a).exists()) {
+                        Ok(t.clone())
+                    } else if let Some(path) = &t.path {
+                        copy_local(t, &path.replace("__VERSION__", version), self.out_dir).map(
+                            |archive| Target {
+                                archive: Some(archive),
+                                ..t.clone()
+                            },
+                        )
+                    } else {
+                        self.download_with_retry(t, version, &multi_progress)
+                            .map(|archive| Target {
+                                archive: Some(archive),
+                                ..t.clone()
+                            })
+                    };
+                    results.lock().expect("poisoned")[i] = Some(res);
+                });
+            }
+        });
+
+        // checksum files are usually shared by all targets, fetch each one once
+        let mut checksum_files = HashMap::new();
+        results
+            .into_inner()
+            .expect("poisoned")
+            .into_iter()
+            .map(|res| {
+                let target = res.expect("every target is downloaded")?;
+                verify(session, &target, version, &mut checksum_files)?;
+                Ok(target)
+            })
+            .collect::<Result<Vec<_>>>()
+    }
+}
+
+/// Bring the local file matching `pattern` into `out_dir`: archives are copied, and
+/// anything else is taken for a raw binary and packed into an archive of its own
+fn copy_local(target: &Target, pattern: &str, out_dir: &Path) -> Result<String> {
+    let mut matches = glob::glob(pattern)
+        .with_context(|| format!("invalid path: '{pattern}'"))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    matches.retain(|p| p.is_file());
+    let file = match matches.as_slice() {
+        [file] => file,
+        [] => bail!("no file matches '{pattern}'"),
+        _ => bail!("'{pattern}' matches several files: {matches:?}"),
+    };
+    let file_name = file
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fs::create_dir_all(out_dir)?;
+
+    if let Some(archive_name) = archive::archive_name(file, &file_name)? {
+        let out_file = out_dir.join(archive_name);
+        fs::copy(file, &out_file)?;
+        return Ok(out_file.to_string_lossy().to_string());
+    }
+
+    let data = fs::read(file)?;
+    let entries = [archive::Entry {
+        path: file_name.clone(),
+        mode: 0o755,
+        data: &data,
+    }];
+    let stem = file_name.trim_end_matches(".exe");
+    let (packed, ext) = match target.platform {
+        Platform::Win32 => (archive::zip(&entries)?, "zip"),
+        _ => (archive::tar_gz(&entries)?, "tar.gz"),
+    };
+    let out_file = out_dir.join(format!("{stem}-{}.{ext}", target.tuple_slug()));
+    fs::write(&out_file, packed)?;
+    Ok(out_file.to_string_lossy().to_string())
+}
+
+/// Parse a `sha256sum`-style checksums file into sha256 by file name
+fn parse_checksums(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (sha, fname) = line.trim().split_once(char::is_whitespace)?;
+            // binary mode entries are marked with a leading '*'
+            let fname = fname.trim_start().trim_start_matches('*');
+            Some((fname.to_string(), sha.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Check a downloaded target against its `sha256`, or its entry in `checksums_url`.
+/// A mismatching archive is removed so it is not picked up again.
+fn verify(
+    session: &mut Session<'_>,
+    target: &Target,
+    version: &str,
+    checksum_files: &mut HashMap<String, HashMap<String, String>>,
+) -> Result<()> {
+    let Some(archive) = target.archive.as_deref() else {
+        return Ok(());
+    };
+    let url = target.url(version);
+    let expected = if let Some(sha) = &target.sha256 {
+        sha.to_lowercase()
+    } else if let Some(checksums_url) = &target.checksums_url {
+        let checksums_url = checksums_url.replace("__VERSION__", version);
+        if !checksum_files.contains_key(&checksums_url) {
+            let content = Download::from_url(&checksums_url).read_to_string()?;
+            checksum_files.insert(checksums_url.clone(), parse_checksums(&content));
+        }
+        // local sources may not have a url, they are listed by their own name
+        let fname = if url.is_empty() {
+            Path::new(archive)
+                .file_name()
+                .map(|f| f.to_string_lossy().to_string())
+                .unwrap_or_default()
+        } else {
+            url.rsplit('/').next().unwrap_or_default().to_string()
+        };
+        checksum_files[&checksums_url]
+            .get(&fname)
+            .with_context(|| format!("'{fname}' is not listed in {checksums_url}"))?
+            .clone()
+    } else {
+        return Ok(());
+    };
+
+    let actual = archive::sha256(Path::new(archive))?;
+    if actual != expected {
+        fs::remove_file(archive)?;
+        bail!("checksum mismatch for '{url}': expected {expected}, got {actual}");
+    }
+    session.console.say(&format!(
+        "   {} {} sha256 verified",
+        FINGER,
+        style(archive).dim()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::MemConsole;
+    use crate::data::Config;
+
+    /// Serve `bodies[0]` then `bodies[1]` as `recon.tar.gz`, each with an `ETag` of its own,
+    /// cutting the first response short. Returns the ranges asked for.
+    fn flaky_server(bodies: [&'static [u8]; 2]) -> (String, thread::JoinHandle<Vec<String>>) {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/recon.tar.gz", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut ranges = vec![];
+            for (i, stream) in listener.incoming().take(2).enumerate() {
+                let mut stream = stream.unwrap();
+                let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+                let (body, etag) = (bodies[i], format!("\"v{}\"", bodies[i].len()));
+                let (mut range, mut if_range) = (String::new(), String::new());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    let line = line.to_lowercase();
+                    if let Some(value) = line.strip_prefix("range: bytes=") {
+                        range = value.trim().trim_end_matches('-').to_string();
+                    }
+                    if let Some(value) = line.strip_prefix("if-range: ") {
+                        if_range = value.trim().to_string();
+                    }
+                }
+                // a range of another object is answered with all of this one
+                let from = if if_range == etag {
+                    range.parse::<usize>().unwrap_or(0)
                 } else {
-                    let url = t.url(version);
-                    let mut d = Download::from_url(&url);
-                    d.show_progress(self.show_progress);
-                    d.download_to(self.out_dir).map(|archive| {
-                        let mut updated = t.clone();
-                        updated.archive = Some(archive);
-                        updated
-                    })
+                    0
+                };
+                let status = if from > 0 {
+                    format!(
+                        "206 Partial Content\r\nContent-Range: bytes {from}-{}/{}",
+                        body.len() - 1,
+                        body.len()
+                    )
+                } else {
+                    "200 OK".to_string()
+                };
+                let rest = &body[from..];
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nETag: {etag}\r\nContent-Length: {}\r\nContent-Disposition: attachment; filename=recon.tar.gz\r\nConnection: close\r\n\r\n",
+                    rest.len()
+                )
+                .unwrap();
+                let sent = if i == 0 {
+                    &rest[..rest.len() / 2]
+                } else {
+                    rest
+                };
+                stream.write_all(sent).unwrap();
+                ranges.push(range);
+            }
+            ranges
+        });
+        (url, handle)
+    }
+
+    fn download_flaky(bodies: [&'static [u8]; 2]) -> (Vec<String>, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, server) = flaky_server(bodies);
+        let targets = [Target {
+            url_template: url,
+            ..Target::default()
+        }];
+        let mut downloader = TargetsDownloader::new(&targets, dir.path()).cache(None);
+        downloader.show_progress = false;
+        let config = Config::default();
+        let mut session = Session {
+            config: &config,
+            console: &mut MemConsole::default(),
+        };
+        let downloaded = downloader.download(&mut session, "1.0.0").unwrap();
+        let archive = downloaded[0].archive.as_ref().unwrap();
+        assert!(archive.ends_with("recon.tar.gz"));
+        // nothing of the unfinished download is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        (server.join().unwrap(), fs::read_to_string(archive).unwrap())
+    }
+
+    #[test]
+    fn test_download_resumes() {
+        let body = b"0123456789abcdefghij";
+        assert_eq!(
+            download_flaky([body, body]),
+            (
+                vec!["".to_string(), "10".to_string()],
+                "0123456789abcdefghij".to_string()
+            )
+        );
+        // re-uploaded in between, the partial file is not spliced with the new object
+        assert_eq!(
+            download_flaky([body, b"ABCDEFGHIJKLMNOPQRSTUVWXYZ"]),
+            (
+                vec!["".to_string(), "10".to_string()],
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZ".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_download_cached() {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/recon.tar.gz", listener.local_addr().unwrap());
+        // serves with an ETag, and answers 304 when it is sent back
+        let server = thread::spawn(move || {
+            let mut statuses = vec![];
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let reader = io::BufReader::new(stream.try_clone().unwrap());
+                let revalidating = reader
+                    .lines()
+                    .map(Result::unwrap)
+                    .take_while(|l| !l.is_empty())
+                    .any(|l| l.to_lowercase() == "if-none-match: \"v1\"");
+                let (status, body) = if revalidating {
+                    ("304 Not Modified", "")
+                } else {
+                    ("200 OK", "recon")
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nETag: \"v1\"\r\nContent-Length: {}\r\nContent-Disposition: attachment; filename=recon.tar.gz\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+                statuses.push(status);
+            }
+            statuses
+        });
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(cache_dir.path());
+        let download = |sha256: Option<String>| {
+            let out = tempfile::tempdir().unwrap();
+            let archive = Download::from_url(&url)
+                .set_cache(Some(cache.clone()))
+                .set_expected_sha256(sha256)
+                .download_to(out.path())
+                .unwrap();
+            fs::read_to_string(archive).unwrap()
+        };
+        assert_eq!(download(None), "recon");
+        assert_eq!(download(None), "recon");
+        // a known sha256 does not even ask the server
+        let sha = format!("{:x}", sha2::Sha256::digest(b"recon"));
+        assert_eq!(download(Some(sha)), "recon");
+        assert_eq!(server.join().unwrap(), vec!["200 OK", "304 Not Modified"]);
+    }
+
+    #[test]
+    fn test_copy_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = dir.path().join("target/x86_64-unknown-linux-gnu/release");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join("recon"), "binary").unwrap();
+        let tar_gz = archive::tar_gz(&[archive::Entry {
+            path: "recon".into(),
+            mode: 0o755,
+            data: b"binary",
+        }])
+        .unwrap();
+        // named without an extension, recognized by content
+        fs::write(dir.path().join("recon-1.0.0-x86_64-linux"), &tar_gz).unwrap();
+        let out = dir.path().join("releases");
+        let target = Target {
+            platform: Platform::Linux,
+            ..Target::default()
+        };
+
+        let packed = copy_local(&target, &build.join("recon").to_string_lossy(), &out).unwrap();
+        assert!(packed.ends_with("recon-linux-x64.tar.gz"));
+        assert_eq!(
+            archive::read_bin(Path::new(&packed), "recon").unwrap(),
+            b"binary"
+        );
+
+        let pattern = dir.path().join("recon-*-x86_64-linux");
+        let copied = copy_local(&target, &pattern.to_string_lossy(), &out).unwrap();
+        assert!(copied.ends_with("recon-1.0.0-x86_64-linux.tar.gz"));
+        assert_eq!(fs::read(copied).unwrap(), tar_gz);
+        assert!(copy_local(&target, &dir.path().join("*.zip").to_string_lossy(), &out).is_err());
+    }
+
+    #[test]
+    fn test_download_names() {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // a plain static server: no Content-Disposition
+        let server = thread::spawn(move || {
+            let body = archive::gzip(b"tarball").unwrap();
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let reader = io::BufReader::new(stream.try_clone().unwrap());
+                for line in reader.lines() {
+                    if line.unwrap().is_empty() {
+                        break;
+                    }
                 }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        let out = tempfile::tempdir().unwrap();
+        let name = |url: &str| {
+            let archive = Download::from_url(url)
+                .set_fallback_name("linux-x64")
+                .download_to(out.path())
+                .unwrap();
+            Path::new(&archive)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(
+            name(&format!("http://{addr}/v1/recon-linux")),
+            "recon-linux.tar.gz"
+        );
+        assert_eq!(name(&format!("http://{addr}/")), "linux-x64.tar.gz");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_download_same_names() {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // serves every path with the path as content
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let reader = io::BufReader::new(stream.try_clone().unwrap());
+                let request = reader.lines().next().unwrap().unwrap();
+                let path = request.split(' ').nth(1).unwrap().to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{path}",
+                    path.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let targets = [
+            Target {
+                platform: Platform::Linux,
+                url_template: format!("http://{addr}/x86_64/tool.tar.gz"),
+                ..Target::default()
+            },
+            Target {
+                platform: Platform::Linux,
+                arch: crate::data::Architecture::ARM64,
+                url_template: format!("http://{addr}/aarch64/tool.tar.gz"),
+                ..Target::default()
+            },
+        ];
+        let mut downloader = TargetsDownloader::new(&targets, dir.path()).cache(None);
+        downloader.show_progress = false;
+        let config = Config::default();
+        let mut session = Session {
+            config: &config,
+            console: &mut MemConsole::default(),
+        };
+        let downloaded = downloader.download(&mut session, "1.0.0").unwrap();
+        server.join().unwrap();
+
+        // whichever finishes second is named after its target
+        let mut names = downloaded
+            .iter()
+            .map(|t| {
+                let archive = Path::new(t.archive.as_ref().unwrap());
+                (
+                    archive.file_name().unwrap().to_string_lossy().to_string(),
+                    fs::read_to_string(archive).unwrap(),
+                )
             })
-            .collect::<Result<Vec<_>>>()
+            .collect::<Vec<_>>();
+        names.sort();
+        assert!(
+            names
+                == [
+                    (
+                        "linux-arm64-tool.tar.gz".into(),
+                        "/aarch64/tool.tar.gz".into()
+                    ),
+                    ("tool.tar.gz".into(), "/x86_64/tool.tar.gz".into()),
+                ]
+                || names
+                    == [
+                        ("linux-x64-tool.tar.gz".into(), "/x86_64/tool.tar.gz".into()),
+                        ("tool.tar.gz".into(), "/aarch64/tool.tar.gz".into()),
+                    ],
+            "{names:?}"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("recon-x86_64-linux.tar.xz");
+        fs::write(&archive, "archive").unwrap();
+        let sha = archive::sha256(&archive).unwrap();
+        let checksums = dir.path().join("SHA256SUMS");
+        fs::write(
+            &checksums,
+            format!("{sha} *recon-x86_64-linux.tar.xz\n{sha}  recon-x86_64-macos.tar.xz\n"),
+        )
+        .unwrap();
+        assert_eq!(
+            parse_checksums(&fs::read_to_string(&checksums).unwrap())["recon-x86_64-linux.tar.xz"],
+            sha
+        );
+
+        let config = Config::default();
+        let mut session = Session {
+            config: &config,
+            console: &mut MemConsole::default(),
+        };
+        let target = Target {
+            url_template: "https://example.com/v__VERSION__/recon-x86_64-linux.tar.xz".into(),
+            archive: Some(archive.to_string_lossy().to_string()),
+            sha256: Some(sha.to_uppercase()),
+            ..Target::default()
+        };
+        verify(&mut session, &target, "1.0.0", &mut HashMap::new()).unwrap();
+
+        // a known checksums file is not fetched again
+        let target = Target {
+            sha256: None,
+            checksums_url: Some("https://example.com/v__VERSION__/SHA256SUMS".into()),
+            ..target
+        };
+        let mut checksum_files = HashMap::from([(
+            "https://example.com/v1.0.0/SHA256SUMS".to_string(),
+            parse_checksums(&format!("{} recon-x86_64-linux.tar.xz", "0".repeat(64))),
+        )]);
+        assert!(verify(&mut session, &target, "1.0.0", &mut checksum_files).is_err());
+        assert!(!archive.exists());
     }
 }
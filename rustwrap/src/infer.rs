@@ -0,0 +1,211 @@
+use crate::data::{Architecture, Libc, Platform, Target};
+use crate::template::VAR_VERSION;
+
+/// Release assets that are not builds of the binary
+const NON_BINARY_SUFFIXES: &[&str] = &[
+    ".sha256",
+    ".sha256sum",
+    ".sha512",
+    ".b3",
+    ".sig",
+    ".asc",
+    ".pem",
+    ".crt",
+    ".txt",
+    ".md",
+    ".json",
+    ".jsonl",
+    ".sbom",
+    ".spdx",
+    ".deb",
+    ".rpm",
+    ".apk",
+    ".msi",
+    ".dmg",
+    ".pkg",
+    ".whl",
+    ".nupkg",
+];
+
+/// Platform, architecture and libc of a Rust target triple, e.g. `aarch64-unknown-linux-musl`,
+/// `None` for triples no provider ships
+pub fn triple(triple: &str) -> Option<(Platform, Architecture, Option<Libc>)> {
+    let arch = match triple.split('-').next()? {
+        "x86_64" => Architecture::X64,
+        "aarch64" => Architecture::ARM64,
+        "i686" | "i586" => Architecture::IA32,
+        "armv7" => Architecture::ARM,
+        "riscv64gc" => Architecture::RISCV64,
+        "s390x" => Architecture::S390X,
+        "powerpc64le" => Architecture::PPC64,
+        "loongarch64" => Architecture::LOONG64,
+        _ => return None,
+    };
+    // android triples also contain `linux`
+    let platform = if triple.contains("windows") {
+        Platform::Win32
+    } else if triple.contains("apple-darwin") {
+        Platform::Darwin
+    } else if triple.contains("android") {
+        Platform::Android
+    } else if triple.contains("linux") {
+        Platform::Linux
+    } else if triple.contains("freebsd") {
+        Platform::FreeBSD
+    } else if triple.contains("netbsd") {
+        Platform::NetBSD
+    } else if triple.contains("openbsd") {
+        Platform::OpenBSD
+    } else if triple.contains("illumos") || triple.contains("solaris") {
+        Platform::SunOS
+    } else {
+        return None;
+    };
+    // glibc is what targets link against unless told otherwise
+    let libc = (platform == Platform::Linux && triple.contains("musl")).then_some(Libc::Musl);
+    Some((platform, arch, libc))
+}
+
+/// Platform, architecture and libc of a release asset by its file name, e.g.
+/// `recon-x86_64-linux.tar.xz`, `recon_darwin_arm64.zip` or a full triple.
+/// Windows assets without an architecture (`recon-windows.zip`) are taken as x64.
+pub fn asset(name: &str) -> Option<(Platform, Architecture, Option<Libc>)> {
+    let name = name.to_lowercase();
+    if NON_BINARY_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+        return None;
+    }
+    let name = name.replace("x86_64", "amd64").replace("x86-64", "amd64");
+    let tokens = name.split(['-', '_', '.']).collect::<Vec<_>>();
+    let has = |words: &[&str]| tokens.iter().any(|t| words.contains(t));
+
+    let platform = if has(&["android"]) {
+        Platform::Android
+    } else if has(&["linux"]) {
+        Platform::Linux
+    } else if has(&["darwin", "macos", "osx", "apple", "mac"]) {
+        Platform::Darwin
+    } else if has(&["windows", "win", "win32", "win64", "msvc", "exe"]) {
+        Platform::Win32
+    } else if has(&["freebsd"]) {
+        Platform::FreeBSD
+    } else if has(&["netbsd"]) {
+        Platform::NetBSD
+    } else if has(&["openbsd"]) {
+        Platform::OpenBSD
+    } else if has(&["illumos", "solaris", "sunos"]) {
+        Platform::SunOS
+    } else {
+        return None;
+    };
+    let arch = if has(&["amd64", "x64", "win64"]) {
+        Architecture::X64
+    } else if has(&["aarch64", "arm64"]) {
+        Architecture::ARM64
+    } else if has(&["i686", "i586", "i386", "386", "x86", "ia32"]) {
+        Architecture::IA32
+    } else if has(&["armv7", "armv7l", "armhf", "arm"]) {
+        Architecture::ARM
+    } else if has(&["riscv64", "riscv64gc"]) {
+        Architecture::RISCV64
+    } else if has(&["s390x"]) {
+        Architecture::S390X
+    } else if has(&["ppc64le", "powerpc64le"]) {
+        Architecture::PPC64
+    } else if has(&["loongarch64", "loong64"]) {
+        Architecture::LOONG64
+    } else if platform == Platform::Win32 {
+        Architecture::X64
+    } else {
+        return None;
+    };
+    let libc = (platform == Platform::Linux && tokens.iter().any(|t| t.starts_with("musl")))
+        .then_some(Libc::Musl);
+    Some((platform, arch, libc))
+}
+
+/// Targets for the `(name, download url)` assets of a release of `version`, one per
+/// platform, architecture and libc, in asset order. The version in urls is turned back
+/// into `__VERSION__`.
+pub fn targets(assets: &[(String, String)], version: &str) -> Vec<Target> {
+    let mut targets: Vec<Target> = vec![];
+    for (name, url) in assets {
+        let Some((platform, arch, libc)) = asset(name) else {
+            continue;
+        };
+        if targets
+            .iter()
+            .any(|t| t.platform == platform && t.arch == arch && t.libc == libc)
+        {
+            continue;
+        }
+        targets.push(Target {
+            platform,
+            arch,
+            libc,
+            url_template: url.replace(version, VAR_VERSION),
+            ..Target::default()
+        });
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triple() {
+        assert_eq!(
+            triple("aarch64-unknown-linux-musl"),
+            Some((Platform::Linux, Architecture::ARM64, Some(Libc::Musl)))
+        );
+        assert_eq!(
+            triple("aarch64-linux-android"),
+            Some((Platform::Android, Architecture::ARM64, None))
+        );
+        assert_eq!(
+            triple("armv7-unknown-linux-gnueabihf"),
+            Some((Platform::Linux, Architecture::ARM, None))
+        );
+        assert_eq!(triple("mips-unknown-linux-gnu"), None);
+    }
+
+    #[test]
+    fn test_targets() {
+        let assets = [
+            "recon-x86_64-linux.tar.xz",
+            "recon-x86_64-linux.tar.xz.sha256",
+            "recon-aarch64-unknown-linux-musl.tar.gz",
+            "recon-aarch64-macos.tar.xz",
+            "recon_darwin_amd64.tar.gz",
+            "recon-windows.zip",
+            "recon-1.2.0-i686-pc-windows-msvc.zip",
+            "SHA256SUMS",
+            "recon_1.2.0_amd64.deb",
+        ]
+        .map(|name| {
+            (
+                name.to_string(),
+                format!(
+                    "https://github.com/rusty-ferris-club/recon/releases/download/v1.2.0/{name}"
+                ),
+            )
+        });
+        let targets = targets(&assets, "1.2.0");
+        assert_eq!(
+            targets.iter().map(Target::tuple_slug).collect::<Vec<_>>(),
+            [
+                "linux-x64",
+                "linux-arm64-musl",
+                "darwin-arm64",
+                "darwin-x64",
+                "win32-x64",
+                "win32-ia32"
+            ]
+        );
+        assert_eq!(
+            targets[5].url_template,
+            "https://github.com/rusty-ferris-club/recon/releases/download/v__VERSION__/recon-__VERSION__-i686-pc-windows-msvc.zip"
+        );
+    }
+}
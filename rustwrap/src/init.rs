@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::console::{style, Console, EnvConsole};
+use crate::data::Target;
+use crate::infer;
+use crate::template::VAR_VERSION;
+
+/// what cargo-binstall assumes when a crate has no `pkg-url`
+const DEFAULT_PKG_URL: &str =
+    "{ repo }/releases/download/v{ version }/{ name }-{ target }-v{ version }{ archive-suffix }";
+const DEFAULT_PKG_FMT: &str = "tgz";
+const DEFAULT_TRIPLES: &[&str] = &[
+    "x86_64-pc-windows-msvc",
+    "x86_64-unknown-linux-gnu",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+];
+
+#[derive(Deserialize, Default)]
+struct Manifest {
+    package: Option<Package>,
+    workspace: Option<Workspace>,
+    #[serde(default)]
+    bin: Vec<Bin>,
+}
+
+#[derive(Deserialize, Default)]
+struct Workspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(rename = "default-members", default)]
+    default_members: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct Package {
+    name: String,
+    description: Option<String>,
+    repository: Option<String>,
+    license: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Deserialize, Default)]
+struct Metadata {
+    binstall: Option<Binstall>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+struct Binstall {
+    #[serde(rename = "pkg-url")]
+    pkg_url: Option<String>,
+    #[serde(rename = "pkg-fmt")]
+    pkg_fmt: Option<String>,
+    #[serde(default)]
+    overrides: BTreeMap<String, Binstall>,
+}
+
+#[derive(Deserialize)]
+struct Bin {
+    name: String,
+}
+
+/// The package `init` scaffolds for, with the binary it ships
+struct Crate {
+    package: Package,
+    bin: String,
+}
+
+/// Find the package shipping a binary: the manifest's own, or the first workspace member with one
+fn find_crate(manifest_path: &Path) -> Result<Crate> {
+    let manifest: Manifest = toml::from_str(&fs::read_to_string(manifest_path)?)
+        .with_context(|| format!("cannot parse {manifest_path:?}"))?;
+    let dir = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+
+    if let Some(package) = manifest.package {
+        let bin = manifest.bin.first().map_or_else(
+            || {
+                dir.join("src/main.rs")
+                    .exists()
+                    .then(|| package.name.clone())
+            },
+            |b| Some(b.name.clone()),
+        );
+        return match bin {
+            Some(bin) => Ok(Crate { package, bin }),
+            None => bail!("'{}' has no binary target", package.name),
+        };
+    }
+
+    let workspace = manifest.workspace.unwrap_or_default();
+    for member in workspace
+        .default_members
+        .iter()
+        .chain(workspace.members.iter())
+    {
+        if let Ok(found) = find_crate(&dir.join(member).join("Cargo.toml")) {
+            return Ok(found);
+        }
+    }
+    bail!("no package with a binary target found in {manifest_path:?}")
+}
+
+fn archive_suffix(pkg_fmt: &str) -> &'static str {
+    match pkg_fmt {
+        "tgz" => ".tar.gz",
+        "txz" => ".tar.xz",
+        "tbz2" => ".tar.bz2",
+        "tzstd" => ".tar.zst",
+        "tar" => ".tar",
+        "zip" => ".zip",
+        _ => "",
+    }
+}
+
+/// `owner/name` of a github repository url
+fn github_repo(repository: &str) -> Option<String> {
+    let path = repository
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .split_once("github.com/")?
+        .1;
+    (path.split('/').count() == 2).then(|| path.to_string())
+}
+
+/// Turn a binstall `pkg-url` into a `url_template`
+fn url_template(pkg_url: &str, pkg_fmt: &str, triple: &str, bin: &str, pkg: &Package) -> String {
+    let repo = pkg
+        .repository
+        .as_deref()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+    let suffix = archive_suffix(pkg_fmt);
+    let binary_ext = if triple.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    let var = Regex::new(r"\{\s*([a-z-]+)\s*\}").expect("regex");
+    var.replace_all(pkg_url, |caps: &regex::Captures<'_>| match &caps[1] {
+        "repo" => repo.to_string(),
+        "version" => VAR_VERSION.to_string(),
+        "name" => pkg.name.clone(),
+        "bin" => bin.to_string(),
+        "target" => triple.to_string(),
+        "archive-suffix" => suffix.to_string(),
+        "archive-format" | "format" => suffix.trim_start_matches('.').to_string(),
+        "binary-ext" => binary_ext.to_string(),
+        _ => caps[0].to_string(),
+    })
+    .to_string()
+}
+
+/// One target per binstall override (or per default target when there are none),
+/// first one wins when several triples map to the same platform and arch
+fn targets(krate: &Crate) -> Vec<Target> {
+    let binstall = krate
+        .package
+        .metadata
+        .as_ref()
+        .and_then(|m| m.binstall.clone())
+        .unwrap_or_default();
+    let triples = if binstall.overrides.is_empty() {
+        DEFAULT_TRIPLES.iter().map(ToString::to_string).collect()
+    } else {
+        binstall.overrides.keys().cloned().collect::<Vec<_>>()
+    };
+
+    let mut targets: Vec<Target> = vec![];
+    for triple in triples {
+        let Some((platform, arch, libc)) = infer::triple(&triple) else {
+            continue;
+        };
+        if targets
+            .iter()
+            .any(|t| t.platform == platform && t.arch == arch && t.libc == libc)
+        {
+            continue;
+        }
+        let over = binstall.overrides.get(&triple);
+        let pkg_url = over
+            .and_then(|o| o.pkg_url.as_deref())
+            .or(binstall.pkg_url.as_deref())
+            .unwrap_or(DEFAULT_PKG_URL);
+        let pkg_fmt = over
+            .and_then(|o| o.pkg_fmt.as_deref())
+            .or(binstall.pkg_fmt.as_deref())
+            .unwrap_or(DEFAULT_PKG_FMT);
+        targets.push(Target {
+            platform,
+            arch,
+            libc,
+            url_template: url_template(pkg_url, pkg_fmt, &triple, &krate.bin, &krate.package),
+            ..Target::default()
+        });
+    }
+    targets
+}
+
+fn npm_root_manifest(pkg: &Package) -> serde_json::Value {
+    let mut manifest = json!({
+        "name": pkg.name,
+        "version": "0.0.0",
+        "description": pkg.description.as_deref().unwrap_or_default(),
+    });
+    let fields = manifest.as_object_mut().expect("object");
+    if let Some(repository) = &pkg.repository {
+        fields.insert("homepage".into(), json!(repository));
+        fields.insert(
+            "bugs".into(),
+            json!(format!("{}/issues", repository.trim_end_matches('/'))),
+        );
+    }
+    if let Some(author) = pkg.authors.first() {
+        fields.insert("author".into(), json!(author));
+    }
+    if let Some(license) = &pkg.license {
+        fields.insert("license".into(), json!(license));
+    }
+    fields.insert("keywords".into(), json!(pkg.keywords));
+    fields.insert("files".into(), json!([]));
+    manifest
+}
+
+/// A starter `rustwrap.yaml`, and the npm manifests it points to, by path
+struct Scaffold {
+    config: String,
+    files: Vec<(PathBuf, String)>,
+}
+
+fn scaffold(manifest_path: &Path, config_dir: &Path) -> Result<Scaffold> {
+    let krate = find_crate(manifest_path)?;
+    let pkg = &krate.package;
+    let targets = targets(&krate);
+    if targets.is_empty() {
+        bail!("no supported targets found for '{}'", pkg.name);
+    }
+    let repo = pkg.repository.as_deref().and_then(github_repo);
+    let owner = repo
+        .as_deref()
+        .and_then(|r| r.split_once('/'))
+        .map_or(pkg.name.as_str(), |(owner, _)| owner);
+
+    let npm_root = config_dir
+        .join("npm")
+        .join(format!("{}-root.json", pkg.name));
+    let npm_sub = config_dir
+        .join("npm")
+        .join(format!("{}-sub.json", pkg.name));
+    let mut npm = json!({
+        "publish": false,
+        "org": format!("@{owner}"),
+        "name": pkg.name,
+        "root": { "manifest": npm_root },
+        "sub": { "manifest": npm_sub },
+    });
+    if krate.bin != pkg.name {
+        npm["bin"] = json!(krate.bin);
+    }
+
+    let mut config = serde_json::Map::new();
+    if let Some(repo) = &repo {
+        config.insert("repo".into(), json!(repo));
+    }
+    config.insert("targets".into(), serde_json::to_value(&targets)?);
+    config.insert("npm".into(), npm);
+    // no recipe template: brew generates its default formula from these
+    let mut brew = json!({
+        "publish": false,
+        "name": pkg.name,
+        "tap": format!("{owner}/homebrew-tap"),
+    });
+    if let Some(description) = &pkg.description {
+        brew["description"] = json!(description);
+    }
+    if let Some(repository) = &pkg.repository {
+        brew["homepage"] = json!(repository);
+    }
+    if let Some(license) = &pkg.license {
+        brew["license"] = json!(license);
+    }
+    if krate.bin != pkg.name {
+        brew["bin"] = json!(krate.bin);
+    }
+    config.insert("brew".into(), brew);
+
+    let sub_manifest = json!({
+        "name": format!("@{owner}/{}", pkg.name),
+        "version": "0.0.0",
+        "files": [],
+    });
+    Ok(Scaffold {
+        config: serde_yaml::to_string(&config)?,
+        files: vec![
+            (npm_root, format!("{:#}\n", npm_root_manifest(pkg))),
+            (npm_sub, format!("{sub_manifest:#}\n")),
+        ],
+    })
+}
+
+/// Write a starter `config_file` for the package in `manifest_path` (a `Cargo.toml`),
+/// along with the npm manifests it points to.
+///
+/// # Errors
+///
+/// This function will return an error if `config_file` exists and `force` is not set,
+/// or no package with a binary target is found
+pub fn init(manifest_path: &Path, config_file: &Path, force: bool) -> Result<()> {
+    if config_file.exists() && !force {
+        bail!("{config_file:?} already exists, use --force to overwrite it");
+    }
+    let config_dir = config_file.parent().unwrap_or_else(|| Path::new(""));
+    let scaffold = scaffold(manifest_path, config_dir)?;
+
+    let mut console = EnvConsole {};
+    for (path, content) in &scaffold.files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        console.say(&format!(
+            "{} wrote {}",
+            crate::console::INFO,
+            style(path.display()).magenta()
+        ));
+    }
+    fs::write(config_file, &scaffold.config)?;
+    console.say(&format!(
+        "{} wrote {}, review it and run `rustwrap --tag <version>`",
+        crate::console::INFO,
+        style(config_file.display()).magenta()
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Registry};
+
+    #[test]
+    fn test_scaffold() {
+        let scaffold = scaffold(Path::new("../Cargo.toml"), Path::new("")).unwrap();
+        assert!(scaffold.config.contains("repo: rusty-ferris-club/rustwrap"));
+        assert!(!scaffold.config.contains("recipe_template"));
+        assert!(scaffold.config.contains("license: Apache-2.0"));
+
+        let config: Config = serde_yaml::from_str(&scaffold.config).unwrap();
+        assert_eq!(config.targets.len(), 4);
+        assert_eq!(
+            config.targets[0].url_template,
+            "https://github.com/rusty-ferris-club/rustwrap/releases/download/v__VERSION__/rustwrap-aarch64-macos.tar.xz"
+        );
+        assert_eq!(Registry::default().load(&config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_url_template() {
+        let pkg = Package {
+            name: "recon".into(),
+            repository: Some("https://github.com/rusty-ferris-club/recon.git".into()),
+            ..Package::default()
+        };
+        assert_eq!(
+            url_template(DEFAULT_PKG_URL, "tgz", "x86_64-unknown-linux-gnu", "recon", &pkg),
+            "https://github.com/rusty-ferris-club/recon/releases/download/v__VERSION__/recon-x86_64-unknown-linux-gnu-v__VERSION__.tar.gz"
+        );
+        assert_eq!(
+            github_repo("https://github.com/rusty-ferris-club/recon/"),
+            Some("rusty-ferris-club/recon".into())
+        );
+    }
+}
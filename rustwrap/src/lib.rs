@@ -3,15 +3,35 @@
 //!
 //! Rustwrap is a tool that helps wrap binary releases for easy distribution. Currently supporting:
 //!* **npm** - `npm install -g your-tool` will make your binary `your-tool` available via the CLI. `rustwrap` creates the necessary binary packages and root package with a Node.js shim that delegates running to your platform-specific bin.
-//!* **Homebrew** - creates a recipe and saves or publishes it to your tap.
+//!* **Homebrew** - creates a recipe for your macOS and Linux targets and saves or publishes it to your tap.
+//!* **Scoop** - creates a manifest for your Windows targets and saves or publishes it to your bucket.
+//!* **winget** - creates the version, locale and installer manifests for your Windows targets, ready to PR into `winget-pkgs`.
+//!* **deb** - builds a `.deb` package per Linux target, installing your binary to `/usr/bin`. No `dpkg-deb` needed.
+//!* **rpm** - builds a binary `.rpm` package per Linux target, installing your binary to `/usr/bin`. No `rpmbuild` needed.
+//!* **AUR** - creates a `PKGBUILD` and `.SRCINFO` for a `<name>-bin` package from your Linux targets and saves or pushes them to the AUR.
+//!* **Nix** - creates a `default.nix` (and optionally a `flake.nix`) fetching your Linux and macOS targets with pinned hashes.
+//!* **PyPI** - `pip install your-tool` will make your binary available via the CLI. `rustwrap` creates one platform-tagged wheel per target and optionally uploads them with `twine`.
+//!* **OCI** - builds a multi-arch container image from your Linux targets as an OCI layout (and optionally an `oci-archive` tarball). No Docker daemon needed.
+//!* **Chocolatey** - generates a `.nuspec` and `chocolateyinstall.ps1` from your `win32` target and packs them into a `.nupkg`, optionally pushing it with `choco push`.
+//!* **Installer** - generates `install.sh` and `install.ps1` scripts that pick the right release for the machine they run on, straight from your `targets`.
 //!
 //!
 #![warn(missing_docs)] // uncomment for docs
 #![allow(clippy::missing_const_for_fn)]
+mod archive;
+mod cache;
 mod console;
 mod data;
 mod download;
+mod infer;
+/// scaffold a configuration from `Cargo.toml`
+pub mod init;
 mod providers;
+mod template;
 
 /// run the main workflow
 pub mod runner;
+
+pub use console::Console;
+pub use data::{Architecture, Config, Libc, Platform, Session, Target};
+pub use providers::{Action, Provider, Registry};
@@ -1,6 +1,6 @@
 #![allow(clippy::must_use_candidate)]
 
-use rustwrap::runner;
+use rustwrap::{init, runner, Registry as Providers};
 
 use anyhow::Result as AnyResult;
 use clap::{crate_version, ArgAction};
@@ -25,6 +25,7 @@ pub fn command() -> Command {
                 .short('c')
                 .long("config")
                 .value_name("CONFIG_FILE")
+                .global(true)
                 .default_value("rustwrap.yaml")
                 .help("Point to a configuration YAML"),
         )
@@ -33,6 +34,7 @@ pub fn command() -> Command {
                 .short('o')
                 .long("out")
                 .value_name("OUT_DIR")
+                .global(true)
                 .default_value("dist")
                 .help("Output directory"),
         )
@@ -41,6 +43,7 @@ pub fn command() -> Command {
                 .short('t')
                 .long("tag")
                 .value_name("VERSION_TAG")
+                .global(true)
                 .help("Version tag to package (e.g. '1.0.1')"),
         )
         .arg(
@@ -50,12 +53,53 @@ pub fn command() -> Command {
                 .help("populate tag with the latest discovered release")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .global(true)
+                .help("Generate everything, print what would be published, publish nothing")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .global(true)
+                .help("Always download archives, without reading or filling the download cache")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("verbose")
                 .long("verbose")
                 .help("Show details about interactions")
                 .action(ArgAction::SetTrue),
         )
+        .subcommand(
+            Command::new("init")
+                .about("Write a starter configuration from Cargo.toml")
+                .arg(
+                    Arg::new("manifest-path")
+                        .long("manifest-path")
+                        .value_name("CARGO_TOML")
+                        .default_value("Cargo.toml")
+                        .help("Cargo.toml of the package, or of its workspace"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Overwrite an existing configuration")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("download").about("Download release archives into the output directory"),
+        )
+        .subcommand(Command::new("generate").about("Generate packages from downloaded archives"))
+        .subcommand(Command::new("publish").about("Publish generated packages"))
+        .subcommand(
+            Command::new("verify")
+                .about("Check downloaded archives and generated packages are ready to publish"),
+        )
+        .subcommand(Command::new("plan").about("Same as --dry-run: show what would be published"))
 }
 
 /// Run
@@ -63,16 +107,30 @@ pub fn command() -> Command {
 /// # Errors
 ///
 /// This function will return an error
-fn run(matches: &ArgMatches) -> AnyResult<bool> {
-    let out_path = matches.get_one::<String>("out");
-    let config_file = matches.get_one::<String>("config");
+fn run(matches: &ArgMatches, dry_run: bool) -> AnyResult<bool> {
+    let out_path = Path::new(matches.get_one::<String>("out").expect("no path"));
+    let config_file = Path::new(matches.get_one::<String>("config").expect("no config"));
     let version = matches.get_one::<String>("tag").cloned();
+    let dry_run = dry_run || matches.get_flag("dry-run");
+    let cache = !matches.get_flag("no-cache");
+    let registry = Providers::default();
 
-    runner::run(
-        version,
-        Path::new(config_file.expect("no config")),
-        Path::new(out_path.expect("no path")),
-    )?;
+    match matches.subcommand() {
+        Some(("download", _)) => runner::download(version, config_file, out_path, cache)?,
+        Some(("generate", _)) => runner::generate(&registry, config_file, out_path)?,
+        Some(("publish", _)) => runner::publish(&registry, config_file, out_path, dry_run)?,
+        Some(("verify", _)) => runner::verify(&registry, config_file, out_path)?,
+        Some(("init", init_matches)) => init::init(
+            Path::new(
+                init_matches
+                    .get_one::<String>("manifest-path")
+                    .expect("no manifest"),
+            ),
+            config_file,
+            init_matches.get_flag("force"),
+        )?,
+        _ => runner::run_with(&registry, version, config_file, out_path, dry_run, cache)?,
+    }
     Ok(true)
 }
 
@@ -99,9 +157,11 @@ fn main() {
         .init();
 
     // actual logic is in 'run'.
-    // subcommand is an error, but you can swap it later if you bring in subcommands
     let res = match matches.subcommand() {
-        None => run(&matches),
+        None | Some(("init" | "download" | "generate" | "publish" | "verify", _)) => {
+            run(&matches, false)
+        }
+        Some(("plan", _)) => run(&matches, true),
         _ => Ok(false),
     };
 
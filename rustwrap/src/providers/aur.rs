@@ -0,0 +1,520 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    archive,
+    console::style,
+    data::{one_per_arch, Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Action, Provider},
+    template::{self, TargetVars},
+};
+
+const PKGBUILD: &str = "PKGBUILD";
+const SRCINFO: &str = ".SRCINFO";
+
+#[derive(Deserialize, Default)]
+pub struct AurOpts {
+    pub name: String,
+    pub maintainer: String,
+    pub description: String,
+    pub license: String,
+    pub url: Option<String>,
+    pub depends: Option<Vec<String>>,
+    pub bin: Option<String>,
+    pub pkgbuild_template: Option<String>,
+    /// write the .SRCINFO of a templated PKGBUILD with `makepkg --printsrcinfo`
+    #[serde(default)]
+    pub makepkg: bool,
+    pub remote: Option<String>,
+    pub publish: bool,
+}
+
+impl AurOpts {
+    fn pkgname(&self) -> String {
+        format!("{}-bin", self.name)
+    }
+
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    fn package_dir(&self, out_dir: &Path, version: &str) -> PathBuf {
+        package_dir(out_dir, &self.name, version, "aur").join(self.pkgname())
+    }
+}
+
+struct TargetDetails {
+    arch: Architecture,
+    url: String,
+    sha: String,
+    root_dir: Option<String>,
+}
+
+fn aur_arch(arch: &Architecture) -> &'static str {
+    match arch {
+        Architecture::X64 => "x86_64",
+        Architecture::ARM64 => "aarch64",
+        Architecture::IA32 => "i686",
+        Architecture::ARM => "armv7h",
+        Architecture::RISCV64 => "riscv64",
+        Architecture::S390X => "s390x",
+        Architecture::PPC64 => "powerpc64le",
+        Architecture::LOONG64 => "loong64",
+    }
+}
+
+/// `-` is not allowed in `pkgver`
+fn aur_version(version: &str) -> String {
+    version.replace('-', "_")
+}
+
+fn quoted(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|i| format!("'{i}'"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `<pkgname>-<pkgver>-<arch>.<ext>::<url>`, so downloads of different arches with the same
+/// file name do not collide in `$srcdir`
+fn source(version: &str, d: &TargetDetails, opts: &AurOpts) -> String {
+    let url = d.url.to_lowercase();
+    let ext = [
+        "tar.gz", "tgz", "tar.xz", "txz", "tar.bz2", "tar.zst", "zip",
+    ]
+    .into_iter()
+    .find(|ext| url.ends_with(&format!(".{ext}")))
+    .unwrap_or("tar.gz");
+    format!(
+        "{}-{}-{}.{ext}::{}",
+        opts.pkgname(),
+        aur_version(version),
+        aur_arch(&d.arch),
+        d.url
+    )
+}
+
+fn pkgbuild(version: &str, details: &[TargetDetails], opts: &AurOpts) -> String {
+    let bin = opts.bin_name();
+    let mut out = String::new();
+    let _ = writeln!(out, "# Maintainer: {}", opts.maintainer);
+    let _ = writeln!(out, "pkgname={}", opts.pkgname());
+    let _ = writeln!(out, "pkgver={}", aur_version(version));
+    let _ = writeln!(out, "pkgrel=1");
+    let _ = writeln!(out, "pkgdesc=\"{}\"", opts.description.replace('"', "\\\""));
+    let _ = writeln!(
+        out,
+        "arch=({})",
+        quoted(
+            &details
+                .iter()
+                .map(|d| aur_arch(&d.arch).to_string())
+                .collect::<Vec<_>>()
+        )
+    );
+    if let Some(url) = &opts.url {
+        let _ = writeln!(out, "url=\"{url}\"");
+    }
+    let _ = writeln!(out, "license=('{}')", opts.license);
+    let _ = writeln!(
+        out,
+        "depends=({})",
+        quoted(opts.depends.as_deref().unwrap_or_default())
+    );
+    let _ = writeln!(out, "provides=('{}')", opts.name);
+    let _ = writeln!(out, "conflicts=('{}')", opts.name);
+    for d in details {
+        let arch = aur_arch(&d.arch);
+        let _ = writeln!(out, "source_{arch}=(\"{}\")", source(version, d, opts));
+        let _ = writeln!(out, "sha256sums_{arch}=('{}')", d.sha);
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "package() {{");
+    let _ = writeln!(out, "  case \"$CARCH\" in");
+    for d in details {
+        let _ = writeln!(
+            out,
+            "    {}) _dir=\"{}\" ;;",
+            aur_arch(&d.arch),
+            d.root_dir.as_deref().unwrap_or(".")
+        );
+    }
+    let _ = writeln!(out, "  esac");
+    let _ = writeln!(
+        out,
+        "  install -Dm755 \"$srcdir/$_dir/{bin}\" \"$pkgdir/usr/bin/{bin}\""
+    );
+    let _ = writeln!(out, "}}");
+    out
+}
+
+fn srcinfo(version: &str, details: &[TargetDetails], opts: &AurOpts) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "pkgbase = {}", opts.pkgname());
+    let _ = writeln!(out, "\tpkgdesc = {}", opts.description);
+    let _ = writeln!(out, "\tpkgver = {}", aur_version(version));
+    let _ = writeln!(out, "\tpkgrel = 1");
+    if let Some(url) = &opts.url {
+        let _ = writeln!(out, "\turl = {url}");
+    }
+    for d in details {
+        let _ = writeln!(out, "\tarch = {}", aur_arch(&d.arch));
+    }
+    let _ = writeln!(out, "\tlicense = {}", opts.license);
+    for dep in opts.depends.iter().flatten() {
+        let _ = writeln!(out, "\tdepends = {dep}");
+    }
+    let _ = writeln!(out, "\tprovides = {}", opts.name);
+    let _ = writeln!(out, "\tconflicts = {}", opts.name);
+    for d in details {
+        let arch = aur_arch(&d.arch);
+        let _ = writeln!(out, "\tsource_{arch} = {}", source(version, d, opts));
+        let _ = writeln!(out, "\tsha256sums_{arch} = {}", d.sha);
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "pkgname = {}", opts.pkgname());
+    out
+}
+
+/// .SRCINFO of a templated PKGBUILD by `makepkg`, when asked for. `None` falls back to
+/// the built-in one, which a template that changes more than the sources can drift from.
+fn makepkg_srcinfo(out_dir: &Path, opts: &AurOpts) -> Option<String> {
+    if !opts.makepkg || opts.pkgbuild_template.is_none() {
+        return None;
+    }
+    match duct::cmd!("makepkg", "--printsrcinfo")
+        .dir(out_dir)
+        .stderr_capture()
+        .read()
+    {
+        Ok(srcinfo) => Some(srcinfo + "\n"),
+        Err(err) => {
+            tracing::warn!(
+                ?err,
+                "cannot run makepkg, writing the built-in .SRCINFO instead"
+            );
+            None
+        }
+    }
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &AurOpts,
+) -> Result<()> {
+    let out_dir = opts.package_dir(out_dir, version);
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("aur").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    Provider::validate(opts)?;
+
+    let linux_targets = one_per_arch(
+        targets.iter().filter(|t| t.platform == Platform::Linux),
+        Libc::Gnu,
+    );
+    let details = linux_targets
+        .iter()
+        .map(|t| {
+            let fname = t
+                .archive
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", t))?;
+            Ok(TargetDetails {
+                arch: t.arch.clone(),
+                url: t.url(version),
+                sha: archive::sha256(Path::new(fname))?,
+                root_dir: archive::root_dir(Path::new(fname))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if details.is_empty() {
+        bail!("no targets available");
+    }
+
+    let pkgbuild = match &opts.pkgbuild_template {
+        Some(t) => template::render(
+            t,
+            &template::Context {
+                name: opts.pkgname(),
+                version: aur_version(version),
+                repo: session.config.repo.clone(),
+                description: Some(opts.description.clone()),
+                homepage: opts.url.clone(),
+                license: Some(opts.license.clone()),
+                targets: linux_targets
+                    .iter()
+                    .map(|t| TargetVars::new(t, version))
+                    .collect::<Result<_>>()?,
+            },
+        )?,
+        None => pkgbuild(version, &details, opts),
+    };
+    tracing::info!(pkgbuild, "rendered PKGBUILD");
+    fs::write(out_dir.join(PKGBUILD), pkgbuild)?;
+    let srcinfo = match makepkg_srcinfo(&out_dir, opts) {
+        Some(srcinfo) => srcinfo,
+        None => srcinfo(version, &details, opts),
+    };
+    fs::write(out_dir.join(SRCINFO), srcinfo)?;
+    session.console.say(&format!(
+        "{} saved {} and {} to '{}'",
+        prefix,
+        PKGBUILD,
+        SRCINFO,
+        style(&out_dir.to_string_lossy()).magenta(),
+    ));
+    Ok(())
+}
+
+pub fn plan(out_dir: &Path, version: &str, opts: &AurOpts) -> Result<Vec<Action>> {
+    let pkg_dir = opts.package_dir(out_dir, version);
+    let remote = opts.remote.as_deref().unwrap_or_default();
+    [PKGBUILD, SRCINFO]
+        .iter()
+        .map(|fname| {
+            fs::metadata(pkg_dir.join(fname))?;
+            Ok(Action::new(format!("{}/{fname}", opts.pkgname()), remote))
+        })
+        .collect()
+}
+
+/// commit the package files into the AUR git remote, using a scratch clone
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &AurOpts,
+) -> Result<()> {
+    let prefix = format!("{} {}", crate::console::PKG, style("aur").green());
+    let pkg_dir = opts.package_dir(out_dir, version);
+    let remote = opts
+        .remote
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("no `remote` configured"))?;
+    let scratch = tempfile::tempdir()?;
+    let repo = scratch.path().join(opts.pkgname());
+    duct::cmd!("git", "clone", "--quiet", remote, &repo).run()?;
+    for fname in [PKGBUILD, SRCINFO] {
+        fs::copy(pkg_dir.join(fname), repo.join(fname))?;
+    }
+
+    let git = |args: &[&str]| {
+        let mut cmd = duct::cmd("git", args).dir(&repo);
+        // commit as the maintainer when it is given as `Name <email>`
+        if let Some((name, email)) = opts.maintainer.split_once('<') {
+            let (name, email) = (name.trim(), email.trim_end_matches('>').trim());
+            cmd = cmd
+                .env("GIT_AUTHOR_NAME", name)
+                .env("GIT_AUTHOR_EMAIL", email)
+                .env("GIT_COMMITTER_NAME", name)
+                .env("GIT_COMMITTER_EMAIL", email);
+        }
+        cmd
+    };
+    git(&["add", PKGBUILD, SRCINFO]).run()?;
+    if git(&["status", "--porcelain"]).read()?.trim().is_empty() {
+        session.console.say(&format!(
+            "{prefix} '{}' is up to date, skipping.",
+            style(remote).magenta()
+        ));
+        return Ok(());
+    }
+    git(&[
+        "commit",
+        "--quiet",
+        "-m",
+        &format!("rustwrap update: {version}"),
+    ])
+    .run()?;
+    git(&["push", "--quiet", "origin", "HEAD:master"]).run()?;
+    session.console.say(&format!(
+        "{} published '{}' to '{}'",
+        prefix,
+        style(opts.pkgname()).magenta(),
+        style(remote).magenta()
+    ));
+    Ok(())
+}
+
+impl Provider for AurOpts {
+    fn name(&self) -> &'static str {
+        "aur"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "aur"))
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.publish && self.remote.is_none() {
+            bail!("publishing requires a `remote` git url");
+        }
+        if let Some(t) = &self.pkgbuild_template {
+            template::validate(t)?;
+        }
+        Ok(())
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        plan(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_generate_and_push() {
+        let out = Path::new("out/aur_test_generate");
+        let _ = fs::remove_dir_all(out);
+        let remote = out.join("remote.git");
+        duct::cmd!("git", "init", "--quiet", "--bare", &remote)
+            .run()
+            .unwrap();
+
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        let opts = AurOpts {
+            name: "recon".to_string(),
+            maintainer: "Dotan Nahum <dotan@rng0.io>".to_string(),
+            description: "recon tool".to_string(),
+            license: "Apache-2.0".to_string(),
+            remote: Some(remote.to_string_lossy().to_string()),
+            publish: true,
+            ..Default::default()
+        };
+        generate(
+            &mut session,
+            out,
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::X64,
+                    url_template: "https://example.com/v__VERSION__/recon-x86_64-linux.tar.gz"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::ARM64,
+                    url_template: "https://example.com/v__VERSION__/recon-aarch64-linux.tar.gz"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-aarch64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+            ],
+            &opts,
+        )
+        .unwrap();
+        publish(&mut session, out, "1.0.1", &opts).unwrap();
+
+        let x64_sha =
+            archive::sha256(Path::new("fixtures/archives/recon-x86_64-linux.tar.gz")).unwrap();
+        let pkgbuild = fs::read_to_string(out.join("recon-1.0.1/aur/recon-bin/PKGBUILD")).unwrap();
+        assert!(pkgbuild.contains("arch=('x86_64' 'aarch64')"));
+        assert!(pkgbuild.contains(
+            "source_x86_64=(\"recon-bin-1.0.1-x86_64.tar.gz::https://example.com/v1.0.1/recon-x86_64-linux.tar.gz\")"
+        ));
+        assert!(pkgbuild.contains(&format!("sha256sums_x86_64=('{x64_sha}')")));
+        assert!(pkgbuild.contains("x86_64) _dir=\"recon-x86_64-linux\" ;;"));
+
+        let clone = out.join("clone");
+        duct::cmd!("git", "clone", "--quiet", &remote, &clone)
+            .run()
+            .unwrap();
+        let srcinfo = fs::read_to_string(out.join("recon-1.0.1/aur/recon-bin/.SRCINFO")).unwrap();
+        assert!(srcinfo.contains(
+            "\tsource_aarch64 = recon-bin-1.0.1-aarch64.tar.gz::https://example.com/v1.0.1/recon-aarch64-linux.tar.gz"
+        ));
+        assert_eq!(fs::read_to_string(clone.join(SRCINFO)).unwrap(), srcinfo);
+    }
+
+    #[test]
+    fn test_template_without_makepkg() {
+        let out = Path::new("out/aur_test_template");
+        let _ = fs::remove_dir_all(out);
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        let opts = AurOpts {
+            name: "recon".to_string(),
+            description: "recon tool".to_string(),
+            license: "Apache-2.0".to_string(),
+            pkgbuild_template: Some("pkgname={{ name }}\n".to_string()),
+            makepkg: true,
+            ..Default::default()
+        };
+        // validating does not need makepkg
+        Provider::validate(&opts).unwrap();
+        generate(
+            &mut session,
+            out,
+            "1.0.1",
+            &[Target {
+                platform: Platform::Linux,
+                arch: Architecture::X64,
+                url_template: "https://example.com/v__VERSION__/recon-x86_64-linux.tar.gz"
+                    .to_string(),
+                archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                ..Default::default()
+            }],
+            &opts,
+        )
+        .unwrap();
+        let pkg_dir = out.join("recon-1.0.1/aur/recon-bin");
+        assert_eq!(
+            fs::read_to_string(pkg_dir.join(PKGBUILD)).unwrap(),
+            "pkgname=recon-bin\n"
+        );
+        // makepkg is missing or rejects this PKGBUILD, the built-in .SRCINFO is written
+        let srcinfo = fs::read_to_string(pkg_dir.join(SRCINFO)).unwrap();
+        assert!(srcinfo.starts_with("pkgbase = recon-bin\n"));
+    }
+}
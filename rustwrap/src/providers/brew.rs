@@ -1,58 +1,74 @@
 #![allow(clippy::module_name_repetitions)]
 use regex::Regex;
 use std::{
-    io::{self, Read},
-    path::Path,
+    fmt::Write,
+    path::{Path, PathBuf},
 };
 
 use crate::{
     console::style,
-    providers::github::{get, put},
+    providers::{
+        diff,
+        github::{read_file, update_file},
+        package_dir, Action, Provider,
+    },
+    template::{self, TargetVars},
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Context, Result};
 use fs_err as fs;
 use serde::Deserialize;
-use serde_json::json;
-use sha2::Digest;
 
-use crate::data::{Architecture, Platform, Session, Target};
-
-const VAR_URL: &str = "__URL__";
-const VAR_SHA: &str = "__SHA__";
-const VAR_VERSION: &str = "__VERSION__";
+use crate::data::{one_per_arch, Architecture, Libc, Platform, Session, Target};
 
 #[derive(Deserialize, Default)]
 pub struct BrewOpts {
     pub name: String,
     pub tap: String,
     pub recipe_fname: Option<String>,
-    pub recipe_template: String,
+    pub recipe_template: Option<String>,
+    /// path of a recipe template, instead of `recipe_template`
+    pub recipe_template_file: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub bin: Option<String>,
     pub publish: bool,
 }
 
 impl BrewOpts {
-    fn validate(&self) -> Result<()> {
-        match (
-            self.recipe_template.contains(VAR_URL),
-            self.recipe_template.contains(VAR_SHA),
-            self.recipe_template.contains(VAR_VERSION),
-        ) {
-            (false, _, _) => bail!("missing URL variable"),
-            (_, false, _) => bail!("missing SHA variable"),
-            (_, _, false) => bail!("missing VERSION variable"),
-            (_, _, _) => {}
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    /// The recipe template, `None` for the built-in formula
+    fn template(&self) -> Result<Option<String>> {
+        match (&self.recipe_template, &self.recipe_template_file) {
+            (Some(_), Some(_)) => bail!("set either `recipe_template` or `recipe_template_file`"),
+            (Some(template), None) => Ok(Some(template.clone())),
+            (None, Some(file)) => fs::read_to_string(file)
+                .map(Some)
+                .with_context(|| format!("cannot read recipe template {file}")),
+            (None, None) => Ok(None),
         }
-        Ok(())
     }
 
-    fn recipe(&self, version: &str, target_details: Vec<(Architecture, String, String)>) -> String {
-        let mut out = self.recipe_template.replace(VAR_VERSION, version);
-        for (arch, url, sha) in target_details {
-            out = out
-                .replace(&format!("{VAR_URL}[{arch}]"), &url)
-                .replace(&format!("{VAR_SHA}[{arch}]"), &sha);
+    fn recipe(&self, session: &Session<'_>, version: &str, targets: &[&Target]) -> Result<String> {
+        let ctx = template::Context {
+            name: self.name.clone(),
+            version: version.to_string(),
+            repo: session.config.repo.clone(),
+            description: self.description.clone(),
+            homepage: self.homepage.clone(),
+            license: self.license.clone(),
+            targets: targets
+                .iter()
+                .map(|t| TargetVars::new(t, version))
+                .collect::<Result<_>>()?,
+        };
+        match self.template()? {
+            Some(template) => template::render(&template, &ctx),
+            None => Ok(default_recipe(&ctx, self)),
         }
-        out
     }
 
     fn recipe_file(&self) -> String {
@@ -62,44 +78,134 @@ impl BrewOpts {
     }
 }
 
-pub fn latest(opts: &BrewOpts) -> Result<semver::Version> {
-    let remote_file = format!(
-        "https://api.github.com/repos/{}/contents/{}",
-        opts.tap,
-        opts.recipe_file()
-    );
-    let resp: serde_json::Value = get(&remote_file)?.json()?;
-    let content = resp
-        .pointer("/content")
-        .and_then(serde_json::Value::as_str)
-        .and_then(|s| base64::decode(s.replace('\n', "")).ok())
-        .and_then(|d| String::from_utf8(d).ok());
-    let v = match content {
-        Some(c) => {
-            let re = Regex::new("version \"(.*)\"").unwrap();
-            let caps = re.captures(c.as_ref());
-            caps.and_then(|cs| cs.get(1))
-                .map(|cap| cap.as_str().to_string())
-        }
-        None => {
-            bail!("no content found at {remote_file}")
+/// `my-tool` -> `MyTool`, as brew names formula classes
+fn formula_class(name: &str) -> String {
+    name.split(['-', '_'])
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map_or_else(String::new, |c| {
+                c.to_uppercase().chain(chars).collect::<String>()
+            })
+        })
+        .collect()
+}
+
+/// quote a ruby string, escaping interpolation
+fn ruby_str(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("#{", "\\#{")
+    )
+}
+
+/// `url` and `sha256` of each target, under `on_arm`/`on_intel` so a platform with a
+/// single build never installs it on the other CPU
+fn write_downloads(out: &mut String, indent: &str, targets: &[&TargetVars]) {
+    for t in targets {
+        let block = if t.arch == "arm64" {
+            "on_arm"
+        } else {
+            "on_intel"
+        };
+        let _ = writeln!(out, "{indent}{block} do");
+        let _ = writeln!(out, "{indent}  url {}", ruby_str(&t.url));
+        let _ = writeln!(out, "{indent}  sha256 {}", ruby_str(&t.sha256));
+        let _ = writeln!(out, "{indent}end");
+    }
+}
+
+/// Whether brew can tell `version` from `url` on its own, as a path or file name part.
+/// Prereleases are left to an explicit `version`.
+fn version_in_url(url: &str, version: &str) -> bool {
+    let re = Regex::new(&format!(r"[/_-]v?{}[/_.-]", regex::escape(version))).unwrap();
+    !version.contains(['-', '+']) && re.is_match(url)
+}
+
+/// A formula for the targets in `ctx`, when there is no recipe template
+fn default_recipe(ctx: &template::Context, opts: &BrewOpts) -> String {
+    let bin = opts.bin_name();
+    let mut out = String::new();
+    let _ = writeln!(out, "# generated by rustwrap");
+    let _ = writeln!(out, "class {} < Formula", formula_class(&opts.name));
+    if let Some(description) = &ctx.description {
+        let _ = writeln!(out, "  desc {}", ruby_str(description));
+    }
+    let homepage = ctx
+        .homepage
+        .clone()
+        .or_else(|| ctx.repo.as_ref().map(|r| format!("https://github.com/{r}")));
+    if let Some(homepage) = homepage {
+        let _ = writeln!(out, "  homepage {}", ruby_str(&homepage));
+    }
+    // `brew audit --strict` rejects a version the urls already give
+    if !ctx
+        .targets
+        .iter()
+        .all(|t| version_in_url(&t.url, &ctx.version))
+    {
+        let _ = writeln!(out, "  version {}", ruby_str(&ctx.version));
+    }
+    if let Some(license) = &ctx.license {
+        let _ = writeln!(out, "  license {}", ruby_str(license));
+    }
+    for (platform, block) in [("darwin", "on_macos"), ("linux", "on_linux")] {
+        let targets = ctx
+            .targets
+            .iter()
+            .filter(|t| t.platform == platform)
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            continue;
         }
-    };
+        let _ = writeln!(out);
+        let _ = writeln!(out, "  {block} do");
+        write_downloads(&mut out, "    ", &targets);
+        let _ = writeln!(out, "  end");
+    }
+    let _ = writeln!(out);
+    let _ = writeln!(out, "  def install");
+    let _ = writeln!(out, "    bin.install {}", ruby_str(&bin));
+    let _ = writeln!(out, "  end");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "  test do");
+    let _ = writeln!(
+        out,
+        "    assert_match version.to_s, shell_output(\"#{{bin}}/{bin} --version\")"
+    );
+    let _ = writeln!(out, "  end");
+    let _ = writeln!(out, "end");
+    out
+}
 
-    v.and_then(|v| semver::Version::parse(&v).ok())
-        .ok_or_else(|| anyhow::format_err!("cannot find version at {remote_file}"))
+/// Version a recipe declares, or the one in its first url, `None` if it has neither
+fn recipe_version(recipe: &str) -> Option<semver::Version> {
+    let re = Regex::new("version \"(.*)\"").unwrap();
+    let url = Regex::new(r#"url "[^"]*?[/_-]v?(\d+\.\d+\.\d+)[/_.-]"#).unwrap();
+    re.captures(recipe)
+        .or_else(|| url.captures(recipe))
+        .and_then(|cs| cs.get(1))
+        .and_then(|cap| semver::Version::parse(cap.as_str()).ok())
 }
 
-pub fn publish(
+/// Version of the recipe currently in the tap, `None` if there is none yet
+pub fn latest(opts: &BrewOpts) -> Result<Option<semver::Version>> {
+    let recipe_file = opts.recipe_file();
+    let Some(recipe) = read_file(&opts.tap, &recipe_file)? else {
+        return Ok(None);
+    };
+    Ok(recipe_version(&recipe))
+}
+
+pub fn generate(
     session: &mut Session<'_>,
     out_dir: &Path,
     version: &str,
     targets: &[Target],
     opts: &BrewOpts,
 ) -> Result<()> {
-    let out_dir = out_dir
-        .join(format!("{}-{version}", opts.name))
-        .join("brew");
+    let out_dir = package_dir(out_dir, &opts.name, version, "brew");
     fs::create_dir_all(&out_dir)?;
 
     let prefix = format!("{} {}", crate::console::COFFEE, style("brew").green());
@@ -109,102 +215,265 @@ pub fn publish(
         style(&out_dir.to_string_lossy()).magenta()
     ));
 
-    opts.validate()?;
+    Provider::validate(opts)?;
 
-    let mac_targets = targets
+    // darwin first, so templates keyed by arch alone keep getting the macOS downloads
+    let brew_arch = |t: &&Target| t.arch == Architecture::X64 || t.arch == Architecture::ARM64;
+    let mut brew_targets = targets
         .iter()
-        .filter(|t| {
-            (t.arch == Architecture::X64 || t.arch == Architecture::ARM64)
-                && t.platform == Platform::Darwin
-        })
+        .filter(|t| t.platform == Platform::Darwin)
+        .filter(brew_arch)
         .collect::<Vec<_>>();
+    brew_targets.extend(one_per_arch(
+        targets
+            .iter()
+            .filter(|t| t.platform == Platform::Linux)
+            .filter(brew_arch),
+        Libc::Gnu,
+    ));
 
-    if mac_targets.is_empty() {
+    if brew_targets.is_empty() {
         anyhow::bail!("no targets available");
     }
-    let mut target_details = Vec::new();
-    for target in mac_targets {
-        //
-        // prep: file name, template, hash, and render the url incl. version
-        // then, render the recipe file (ruby source)
-        //
-        let fname = target
-            .archive
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
-
-        let mut file = fs::File::open(fname)?;
-
-        let mut hasher = sha2::Sha256::new();
-        io::copy(&mut file, &mut hasher)?;
-        let hash = hasher.finalize();
-        let sha = format!("{hash:x}");
-        target_details.push((target.arch.clone(), target.url(version), sha));
-    }
-    let recipe = opts.recipe(version, target_details);
+    let recipe = opts.recipe(session, version, &brew_targets)?;
     tracing::info!(recipe, "rendered recipe");
 
     //
-    // post the rendered file to github
+    // save rendered file to disk
     //
-    let fname = opts.recipe_file();
+    let dest_file = out_dir.join(opts.recipe_file());
+    fs::write(&dest_file, recipe)?;
+    session.console.say(&format!(
+        "{} saved recipe to '{}'",
+        prefix,
+        style(&dest_file.to_string_lossy()).magenta(),
+    ));
 
-    if opts.publish {
-        let remote_file = format!("https://api.github.com/repos/{}/contents/{fname}", opts.tap);
-        let resp = get(&remote_file)?;
-
-        let sha = match resp.status() {
-            reqwest::StatusCode::OK => match resp.json::<serde_json::Value>() {
-                Ok(parsed) => parsed
-                    .pointer("/sha")
-                    .ok_or_else(|| anyhow!("no `sha` in response"))?
-                    .as_str()
-                    .map(std::string::ToString::to_string),
-                Err(_) => None,
-            },
-            _ => None,
-        };
+    Ok(())
+}
 
-        let mut res = put(
-            &remote_file,
-            &json!({"message": format!("rustwrap update: {fname}"), "content": base64::encode(&recipe), "sha": sha}),
-        )?;
+fn generated_recipe(out_dir: &Path, version: &str, opts: &BrewOpts) -> Result<String> {
+    Ok(fs::read_to_string(
+        package_dir(out_dir, &opts.name, version, "brew").join(opts.recipe_file()),
+    )?)
+}
 
-        if !res.status().is_success() {
-            let mut response_body = String::new();
-            res.read_to_string(&mut response_body)?;
-            tracing::info!(response_body, "response");
-            bail!("{} publishing with status: {:?}", prefix, res.status());
-        }
+/// The recipe update, with a diff against the recipe currently in the tap
+pub fn plan(out_dir: &Path, version: &str, opts: &BrewOpts) -> Result<Vec<Action>> {
+    let fname = opts.recipe_file();
+    let recipe = generated_recipe(out_dir, version, opts)?;
+    let current = read_file(&opts.tap, &fname)?;
+    Ok(vec![Action {
+        diff: diff(&fname, current.as_deref(), &recipe),
+        ..Action::new(&fname, format!("github.com/{}", opts.tap))
+    }])
+}
 
-        //
-        // we're done
-        //
-        session.console.say(&format!(
-            "{} published '{}' in '{}'",
-            prefix,
-            style(&fname).magenta(),
-            style(&opts.tap).magenta()
-        ));
-    }
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &BrewOpts,
+) -> Result<()> {
+    let fname = opts.recipe_file();
+    let recipe = generated_recipe(out_dir, version, opts)?;
 
     //
-    // save rendered file to disk
+    // post the rendered file to github
     //
-    let dest_file = out_dir.join(&fname);
-    fs::write(&dest_file, recipe)?;
+    update_file(&opts.tap, &fname, &recipe)?;
     session.console.say(&format!(
-        "{} saved recipe to '{}'",
-        prefix,
-        style(&dest_file.to_string_lossy()).magenta(),
+        "{} {} published '{}' in '{}'",
+        crate::console::COFFEE,
+        style("brew").green(),
+        style(&fname).magenta(),
+        style(&opts.tap).magenta()
     ));
-
     Ok(())
 }
 
+impl Provider for BrewOpts {
+    fn name(&self) -> &'static str {
+        "brew"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "brew"))
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self.template()? {
+            Some(template) => template::validate(&template),
+            None => Ok(()),
+        }
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn latest_version(&self) -> Result<Option<semver::Version>> {
+        latest(self)
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        generated_recipe(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{archive, console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_default_recipe() {
+        let config = Config {
+            repo: Some("rusty-ferris-club/recon".to_string()),
+            ..Default::default()
+        };
+        let mut session = Session {
+            config: &config,
+            console: &mut MemConsole::default(),
+        };
+        let target = |platform, arch, fname: &str| Target {
+            platform,
+            arch,
+            url_template: format!("https://example.com/v__VERSION__/{fname}"),
+            archive: Some(format!("fixtures/archives/{fname}")),
+            ..Default::default()
+        };
+        let out = Path::new("out/brew_test_default_recipe");
+        let opts = BrewOpts {
+            name: "recon-tool".to_string(),
+            description: Some("Find, locate, and query files".to_string()),
+            license: Some("Apache-2.0".to_string()),
+            bin: Some("recon".to_string()),
+            ..Default::default()
+        };
+        generate(
+            &mut session,
+            out,
+            "1.0.1",
+            &[
+                // linux has a single build, still guarded by its cpu
+                target(
+                    Platform::Linux,
+                    Architecture::X64,
+                    "recon-x86_64-linux.tar.gz",
+                ),
+                target(
+                    Platform::Darwin,
+                    Architecture::X64,
+                    "recon-x86_64-macos.tar.gz",
+                ),
+                target(
+                    Platform::Darwin,
+                    Architecture::ARM64,
+                    "recon-aarch64-macos.tar.gz",
+                ),
+            ],
+            &opts,
+        )
+        .unwrap();
+
+        let sha =
+            |fname: &str| archive::sha256(&Path::new("fixtures/archives").join(fname)).unwrap();
+        assert_eq!(
+            generated_recipe(out, "1.0.1", &opts).unwrap(),
+            format!(
+                r##"# generated by rustwrap
+class ReconTool < Formula
+  desc "Find, locate, and query files"
+  homepage "https://github.com/rusty-ferris-club/recon"
+  license "Apache-2.0"
+
+  on_macos do
+    on_intel do
+      url "https://example.com/v1.0.1/recon-x86_64-macos.tar.gz"
+      sha256 "{}"
+    end
+    on_arm do
+      url "https://example.com/v1.0.1/recon-aarch64-macos.tar.gz"
+      sha256 "{}"
+    end
+  end
+
+  on_linux do
+    on_intel do
+      url "https://example.com/v1.0.1/recon-x86_64-linux.tar.gz"
+      sha256 "{}"
+    end
+  end
+
+  def install
+    bin.install "recon"
+  end
+
+  test do
+    assert_match version.to_s, shell_output("#{{bin}}/recon --version")
+  end
+end
+"##,
+                sha("recon-x86_64-macos.tar.gz"),
+                sha("recon-aarch64-macos.tar.gz"),
+                sha("recon-x86_64-linux.tar.gz")
+            )
+        );
+    }
+
+    #[test]
+    fn test_version_in_url() {
+        let url = "https://github.com/o/recon/releases/download/v1.0.1/recon-x86_64-linux.tar.gz";
+        assert!(version_in_url(url, "1.0.1"));
+        assert!(version_in_url(
+            "https://example.com/recon-1.0.1-x86_64-linux.tar.gz",
+            "1.0.1"
+        ));
+        assert!(!version_in_url(url, "1.0.10"));
+        assert!(!version_in_url(
+            "https://example.com/latest/recon-x86_64-linux.tar.gz",
+            "1.0.1"
+        ));
+        assert!(!version_in_url(
+            "https://example.com/v1.0.1-rc.1/recon.tar.gz",
+            "1.0.1-rc.1"
+        ));
+    }
+
+    #[test]
+    fn test_recipe_version() {
+        assert_eq!(
+            recipe_version("class Recon < Formula\n  version \"1.0.1\"\nend\n"),
+            Some(semver::Version::new(1, 0, 1))
+        );
+        assert_eq!(
+            recipe_version(
+                "  on_intel do\n    url \"https://example.com/v1.2.0/recon-x86_64-linux.tar.gz\"\n"
+            ),
+            Some(semver::Version::new(1, 2, 0))
+        );
+        assert_eq!(recipe_version("class Recon < Formula\nend\n"), None);
+    }
 
     #[test]
     #[cfg(target_os = "macos")]
@@ -212,10 +481,9 @@ mod tests {
         let v = latest(&BrewOpts {
             name: "rustwrap".to_string(),
             tap: "rusty-ferris-club/homebrew-tap".to_string(),
-            recipe_fname: None,
-            recipe_template: String::new(),
-            publish: false,
+            ..Default::default()
         })
+        .unwrap()
         .unwrap();
         assert!(v > semver::Version::parse("0.0.1").unwrap());
     }
@@ -0,0 +1,367 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::Deserialize;
+use sha2::Digest;
+
+use crate::{
+    archive::{self, Entry},
+    console::style,
+    data::{Architecture, Platform, Session, Target},
+    providers::{package_dir, Action, Provider},
+};
+
+const INSTALL_SCRIPT: &str = "tools/chocolateyinstall.ps1";
+/// where `choco push` pushes to without a `--source`
+const DEFAULT_SOURCE: &str = "https://push.chocolatey.org/";
+
+#[derive(Deserialize, Default)]
+pub struct ChocolateyOpts {
+    pub name: String,
+    pub title: Option<String>,
+    pub authors: String,
+    pub description: String,
+    pub summary: Option<String>,
+    pub project_url: Option<String>,
+    pub license_url: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub source: Option<String>,
+    pub publish: bool,
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn nuspec(version: &str, opts: &ChocolateyOpts) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<package xmlns="http://schemas.microsoft.com/packaging/2015/06/nuspec.xsd">"#
+    );
+    let _ = writeln!(out, "  <metadata>");
+    let mut field = |tag: &str, value: &str| {
+        let _ = writeln!(out, "    <{tag}>{}</{tag}>", xml_escape(value));
+    };
+    field("id", &opts.name);
+    field("version", version);
+    field("title", opts.title.as_ref().unwrap_or(&opts.name));
+    field("authors", &opts.authors);
+    if let Some(project_url) = &opts.project_url {
+        field("projectUrl", project_url);
+    }
+    if let Some(license_url) = &opts.license_url {
+        field("licenseUrl", license_url);
+    }
+    field("requireLicenseAcceptance", "false");
+    if let Some(tags) = &opts.tags {
+        field("tags", &tags.join(" "));
+    }
+    if let Some(summary) = &opts.summary {
+        field("summary", summary);
+    }
+    field("description", &opts.description);
+    let _ = writeln!(out, "  </metadata>");
+    let _ = writeln!(out, "</package>");
+    out
+}
+
+/// install script for the x64 download and, when there is one, the 32-bit download
+fn install_script(x64: Option<(String, String)>, x86: Option<(String, String)>) -> String {
+    let mut args = String::new();
+    if let Some((url, sha)) = x86 {
+        let _ = writeln!(args, "  url            = '{url}'");
+        let _ = writeln!(args, "  checksum       = '{sha}'");
+        let _ = writeln!(args, "  checksumType   = 'sha256'");
+    }
+    if let Some((url, sha)) = x64 {
+        let _ = writeln!(args, "  url64bit       = '{url}'");
+        let _ = writeln!(args, "  checksum64     = '{sha}'");
+        let _ = writeln!(args, "  checksumType64 = 'sha256'");
+    }
+    format!(
+        r#"$ErrorActionPreference = 'Stop'
+$toolsDir = "$(Split-Path -parent $MyInvocation.MyCommand.Definition)"
+
+$packageArgs = @{{
+  packageName    = $env:ChocolateyPackageName
+  unzipLocation  = $toolsDir
+{args}}}
+
+Install-ChocolateyZipPackage @packageArgs
+"#
+    )
+}
+
+/// OPC parts NuGet expects next to the nuspec: content types, relationships and core properties
+fn opc_parts(version: &str, nuspec: &str, opts: &ChocolateyOpts) -> Vec<(String, String)> {
+    let psmdcp = format!(
+        "package/services/metadata/core-properties/{}.psmdcp",
+        &format!("{:x}", sha2::Sha256::digest(nuspec))[..32]
+    );
+    let content_types = concat!(
+        r#"<?xml version="1.0" encoding="utf-8"?>"#,
+        r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+        r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml" />"#,
+        r#"<Default Extension="nuspec" ContentType="application/octet" />"#,
+        r#"<Default Extension="ps1" ContentType="application/octet" />"#,
+        r#"<Default Extension="psmdcp" ContentType="application/vnd.openxmlformats-package.core-properties+xml" />"#,
+        r#"</Types>"#
+    );
+    let rels = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            r#"<Relationship Type="http://schemas.microsoft.com/packaging/2010/07/manifest" Target="/{}.nuspec" Id="R0" />"#,
+            r#"<Relationship Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="/{}" Id="R1" />"#,
+            r#"</Relationships>"#
+        ),
+        opts.name, psmdcp
+    );
+    let core_properties = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="utf-8"?>"#,
+            r#"<coreProperties xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns="http://schemas.openxmlformats.org/package/2006/metadata/core-properties">"#,
+            r#"<dc:creator>{}</dc:creator><dc:description>{}</dc:description><dc:identifier>{}</dc:identifier>"#,
+            r#"<version>{}</version><keywords>{}</keywords><lastModifiedBy>rustwrap</lastModifiedBy>"#,
+            r#"</coreProperties>"#
+        ),
+        xml_escape(&opts.authors),
+        xml_escape(&opts.description),
+        xml_escape(&opts.name),
+        xml_escape(version),
+        xml_escape(&opts.tags.as_deref().unwrap_or_default().join(" ")),
+    );
+    vec![
+        ("[Content_Types].xml".to_string(), content_types.to_string()),
+        ("_rels/.rels".to_string(), rels),
+        (psmdcp, core_properties),
+    ]
+}
+
+impl ChocolateyOpts {
+    fn nupkg_file(&self, version: &str) -> String {
+        format!("{}.{version}.nupkg", self.name)
+    }
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &ChocolateyOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "chocolatey");
+    fs::create_dir_all(out_dir.join("tools"))?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("chocolatey").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    // chocolatey installs x64 packages on arm64 Windows through emulation
+    let download = |arch: Architecture| -> Result<Option<(String, String)>> {
+        let Some(target) = targets
+            .iter()
+            .find(|t| t.platform == Platform::Win32 && t.arch == arch)
+        else {
+            return Ok(None);
+        };
+        let fname = target
+            .archive
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+        Ok(Some((
+            target.url(version),
+            archive::sha256(Path::new(fname))?,
+        )))
+    };
+    let x64 = download(Architecture::X64)?;
+    let x86 = download(Architecture::IA32)?;
+    if x64.is_none() && x86.is_none() {
+        bail!("no targets available");
+    }
+
+    let nuspec = nuspec(version, opts);
+    let install_script = install_script(x64, x86);
+    let nuspec_name = format!("{}.nuspec", opts.name);
+    fs::write(out_dir.join(&nuspec_name), &nuspec)?;
+    fs::write(out_dir.join(INSTALL_SCRIPT), &install_script)?;
+
+    let parts = opc_parts(version, &nuspec, opts);
+    let mut entries = vec![
+        Entry {
+            path: nuspec_name,
+            mode: 0o644,
+            data: nuspec.as_bytes(),
+        },
+        Entry {
+            path: INSTALL_SCRIPT.to_string(),
+            mode: 0o644,
+            data: install_script.as_bytes(),
+        },
+    ];
+    entries.extend(parts.iter().map(|(path, content)| Entry {
+        path: path.clone(),
+        mode: 0o644,
+        data: content.as_bytes(),
+    }));
+    let nupkg = out_dir.join(opts.nupkg_file(version));
+    fs::write(&nupkg, archive::zip(&entries)?)?;
+    session.console.say(&format!(
+        "   {} {}",
+        style("package").yellow(),
+        style(&nupkg.to_string_lossy()).magenta()
+    ));
+
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+pub fn plan(out_dir: &Path, version: &str, opts: &ChocolateyOpts) -> Result<Vec<Action>> {
+    let nupkg = opts.nupkg_file(version);
+    fs::metadata(package_dir(out_dir, &opts.name, version, "chocolatey").join(&nupkg))?;
+    Ok(vec![Action::new(
+        nupkg,
+        opts.source.as_deref().unwrap_or(DEFAULT_SOURCE),
+    )])
+}
+
+/// Push the generated `.nupkg` with `choco push`
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &ChocolateyOpts,
+) -> Result<()> {
+    let nupkg =
+        package_dir(out_dir, &opts.name, version, "chocolatey").join(opts.nupkg_file(version));
+    let mut args = vec!["push".into(), nupkg.into_os_string()];
+    if let Some(source) = &opts.source {
+        args.push("--source".into());
+        args.push(source.into());
+    }
+    let out = duct::cmd("choco", args).read()?;
+    session.console.say(&format!(
+        "{} {} published:\n{out}",
+        crate::console::PKG,
+        style("chocolatey").green()
+    ));
+    Ok(())
+}
+
+impl Provider for ChocolateyOpts {
+    fn name(&self) -> &'static str {
+        "chocolatey"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "chocolatey"))
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        plan(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/chocolatey_test_generate"),
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::X64,
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::X64,
+                    url_template: "https://example.com/v__VERSION__/recon-x86_64-windows.zip"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-x86_64-windows.zip".to_string()),
+                    ..Default::default()
+                },
+            ],
+            &ChocolateyOpts {
+                name: "recon".to_string(),
+                authors: "Dotan Nahum".to_string(),
+                description: "recon <tool> & friends".to_string(),
+                tags: Some(vec!["cli".to_string(), "recon".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let out = Path::new("out/chocolatey_test_generate/recon-1.0.1/chocolatey");
+        let sha = archive::sha256(Path::new("fixtures/archives/recon-x86_64-windows.zip")).unwrap();
+        let mut nupkg =
+            zip::ZipArchive::new(fs::File::open(out.join("recon.1.0.1.nupkg")).unwrap()).unwrap();
+        let mut names = nupkg.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names[..2], ["[Content_Types].xml", "_rels/.rels"]);
+        assert!(names[2].starts_with("package/services/metadata/core-properties/"));
+        assert_eq!(names[3..], ["recon.nuspec", INSTALL_SCRIPT]);
+
+        let mut script = String::new();
+        nupkg
+            .by_name(INSTALL_SCRIPT)
+            .unwrap()
+            .read_to_string(&mut script)
+            .unwrap();
+        assert!(script
+            .contains("url64bit       = 'https://example.com/v1.0.1/recon-x86_64-windows.zip'"));
+        assert!(script.contains(&format!("checksum64     = '{sha}'")));
+
+        let nuspec = fs::read_to_string(out.join("recon.nuspec")).unwrap();
+        assert!(nuspec.contains("<description>recon &lt;tool&gt; &amp; friends</description>"));
+        assert!(nuspec.contains("<tags>cli recon</tags>"));
+    }
+}
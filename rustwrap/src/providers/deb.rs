@@ -0,0 +1,280 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    archive::{self, Entry},
+    console::style,
+    data::{one_per_arch, Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+const DEBIAN_BINARY: &[u8] = b"2.0\n";
+
+#[derive(Deserialize, Default)]
+pub struct DebOpts {
+    pub name: String,
+    pub maintainer: String,
+    pub description: String,
+    pub depends: Option<Vec<String>>,
+    pub section: Option<String>,
+    pub priority: Option<String>,
+    pub homepage: Option<String>,
+    pub bin: Option<String>,
+}
+
+impl DebOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+}
+
+fn deb_arch(arch: &Architecture) -> &'static str {
+    match arch {
+        Architecture::X64 => "amd64",
+        Architecture::ARM64 => "arm64",
+        Architecture::IA32 => "i386",
+        Architecture::ARM => "armhf",
+        Architecture::RISCV64 => "riscv64",
+        Architecture::S390X => "s390x",
+        Architecture::PPC64 => "ppc64el",
+        Architecture::LOONG64 => "loong64",
+    }
+}
+
+/// semver pre-releases sort before the release, which is `~` in debian versions
+fn deb_version(version: &str) -> String {
+    version.replace('-', "~")
+}
+
+fn control(version: &str, arch: &Architecture, installed_size: usize, opts: &DebOpts) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Package: {}", opts.name);
+    let _ = writeln!(out, "Version: {}", deb_version(version));
+    let _ = writeln!(out, "Architecture: {}", deb_arch(arch));
+    let _ = writeln!(out, "Maintainer: {}", opts.maintainer);
+    let _ = writeln!(out, "Installed-Size: {}", installed_size.div_ceil(1024));
+    if let Some(depends) = opts.depends.as_ref().filter(|d| !d.is_empty()) {
+        let _ = writeln!(out, "Depends: {}", depends.join(", "));
+    }
+    let _ = writeln!(
+        out,
+        "Section: {}",
+        opts.section.as_deref().unwrap_or("utils")
+    );
+    let _ = writeln!(
+        out,
+        "Priority: {}",
+        opts.priority.as_deref().unwrap_or("optional")
+    );
+    if let Some(homepage) = &opts.homepage {
+        let _ = writeln!(out, "Homepage: {homepage}");
+    }
+
+    // first line is the synopsis, the rest is indented and empty lines become ' .'
+    let mut lines = opts.description.trim().lines();
+    let _ = writeln!(out, "Description: {}", lines.next().unwrap_or_default());
+    for line in lines {
+        let line = line.trim_end();
+        let _ = writeln!(out, " {}", if line.is_empty() { "." } else { line });
+    }
+    out
+}
+
+fn build(version: &str, target: &Target, opts: &DebOpts) -> Result<Vec<u8>> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let bin_name = target.bin_name(&opts.bin_name()).to_string();
+    let bin = archive::read_bin(Path::new(fname), &bin_name)?;
+
+    let control = control(version, &target.arch, bin.len(), opts);
+    tracing::info!(control, "rendered control");
+    let control_tar = archive::tar_gz(&[Entry {
+        path: "control".to_string(),
+        mode: 0o644,
+        data: control.as_bytes(),
+    }])?;
+    let data_tar = archive::tar_gz(&[Entry {
+        path: format!("usr/bin/{bin_name}"),
+        mode: 0o755,
+        data: &bin,
+    }])?;
+
+    let mut deb = ar::Builder::new(Vec::new());
+    for (name, data) in [
+        ("debian-binary", DEBIAN_BINARY),
+        ("control.tar.gz", control_tar.as_slice()),
+        ("data.tar.gz", data_tar.as_slice()),
+    ] {
+        let mut header = ar::Header::new(name.as_bytes().to_vec(), data.len() as u64);
+        header.set_mode(0o100_644);
+        deb.append(&header, data)?;
+    }
+    Ok(deb.into_inner()?)
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &DebOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "deb");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("deb").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let linux_targets = one_per_arch(
+        targets.iter().filter(|t| t.platform == Platform::Linux),
+        Libc::Gnu,
+    );
+    if linux_targets.is_empty() {
+        bail!("no targets available");
+    }
+
+    for target in linux_targets {
+        let deb = build(version, target, opts)?;
+        let deb_name = format!(
+            "{}_{}_{}.deb",
+            opts.name,
+            deb_version(version),
+            deb_arch(&target.arch)
+        );
+        fs::write(out_dir.join(&deb_name), deb)?;
+        session
+            .console
+            .say(&format!("   {} {}", style("package").yellow(), &deb_name));
+    }
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+impl Provider for DebOpts {
+    fn name(&self) -> &'static str {
+        "deb"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "deb"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use flate2::read::GzDecoder;
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/deb_test_generate"),
+            "1.0.1-beta.1",
+            &[Target {
+                platform: Platform::Linux,
+                arch: Architecture::ARM64,
+                archive: Some("fixtures/archives/recon-aarch64-linux.tar.gz".to_string()),
+                ..Default::default()
+            }],
+            &DebOpts {
+                name: "recon".to_string(),
+                maintainer: "Dotan Nahum <dotan@rng0.io>".to_string(),
+                description: "recon tool\nfind files\n\nwith SQL".to_string(),
+                depends: Some(vec!["libc6".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let deb = fs::File::open(
+            "out/deb_test_generate/recon-1.0.1-beta.1/deb/recon_1.0.1~beta.1_arm64.deb",
+        )
+        .unwrap();
+        let mut ar = ar::Archive::new(deb);
+        let mut members = Vec::new();
+        let mut control = String::new();
+        let mut data_paths = Vec::new();
+        while let Some(entry) = ar.next_entry() {
+            let entry = entry.unwrap();
+            let name = String::from_utf8(entry.header().identifier().to_vec()).unwrap();
+            match name.as_str() {
+                "control.tar.gz" => {
+                    let mut tar = tar::Archive::new(GzDecoder::new(entry));
+                    let mut file = tar.entries().unwrap().next().unwrap().unwrap();
+                    file.read_to_string(&mut control).unwrap();
+                }
+                "data.tar.gz" => {
+                    let mut tar = tar::Archive::new(GzDecoder::new(entry));
+                    for file in tar.entries().unwrap() {
+                        let file = file.unwrap();
+                        data_paths.push((
+                            file.path().unwrap().to_string_lossy().to_string(),
+                            file.header().mode().unwrap(),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+            members.push(name);
+        }
+        assert_eq!(
+            members,
+            vec!["debian-binary", "control.tar.gz", "data.tar.gz"]
+        );
+        assert_eq!(
+            control,
+            "Package: recon
+Version: 1.0.1~beta.1
+Architecture: arm64
+Maintainer: Dotan Nahum <dotan@rng0.io>
+Installed-Size: 1
+Depends: libc6
+Section: utils
+Priority: optional
+Description: recon tool
+ find files
+ .
+ with SQL
+"
+        );
+        assert_eq!(
+            data_paths,
+            vec![
+                ("usr/".to_string(), 0o755),
+                ("usr/bin/".to_string(), 0o755),
+                ("usr/bin/recon".to_string(), 0o755),
+            ]
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use std::env;
+use std::{env, io::Read};
 
 use anyhow::{bail, Context, Result};
 use reqwest::header;
+use serde_json::json;
 use tracing::info;
 
 pub fn put(url: &str, val: &serde_json::Value) -> Result<reqwest::blocking::Response> {
@@ -43,6 +44,61 @@ pub fn api_headers() -> Result<header::HeaderMap> {
 
     Ok(headers)
 }
+/// Read a file from a repo through the GitHub contents API, `None` if it does not exist
+pub fn read_file(repo: &str, path: &str) -> Result<Option<String>> {
+    let remote_file = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let resp = get(&remote_file)?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        bail!(
+            "api request failed with status: {:?} - for: {:?}",
+            resp.status(),
+            remote_file
+        )
+    }
+    let json = resp.json::<serde_json::Value>()?;
+    let content = json
+        .pointer("/content")
+        .and_then(serde_json::Value::as_str)
+        .and_then(|s| base64::decode(s.replace('\n', "")).ok())
+        .and_then(|d| String::from_utf8(d).ok())
+        .ok_or_else(|| anyhow::format_err!("no content found at {remote_file}"))?;
+    Ok(Some(content))
+}
+
+/// Create or update a file in a repo through the GitHub contents API
+pub fn update_file(repo: &str, path: &str, content: &str) -> Result<()> {
+    let remote_file = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let resp = get(&remote_file)?;
+
+    let sha = match resp.status() {
+        reqwest::StatusCode::OK => match resp.json::<serde_json::Value>() {
+            Ok(parsed) => parsed
+                .pointer("/sha")
+                .ok_or_else(|| anyhow::format_err!("no `sha` in response"))?
+                .as_str()
+                .map(std::string::ToString::to_string),
+            Err(_) => None,
+        },
+        _ => None,
+    };
+
+    let mut res = put(
+        &remote_file,
+        &json!({"message": format!("rustwrap update: {path}"), "content": base64::encode(content), "sha": sha}),
+    )?;
+
+    if !res.status().is_success() {
+        let mut response_body = String::new();
+        res.read_to_string(&mut response_body)?;
+        info!(response_body, "response");
+        bail!("publishing {path} with status: {:?}", res.status());
+    }
+    Ok(())
+}
+
 pub fn latest(repo: &str) -> Result<semver::Version> {
     let api_url = format!("https://api.github.com/repos/{repo}/releases/latest");
     let resp = get(&api_url)?;
@@ -62,8 +118,44 @@ pub fn latest(repo: &str) -> Result<semver::Version> {
     semver::Version::parse(tag.trim_start_matches('v')).context("cannot parse version")
 }
 
+/// `(name, download url)` of every asset of the release of `version`, tagged
+/// `v<version>` or `<version>`
+pub fn release_assets(repo: &str, version: &str) -> Result<Vec<(String, String)>> {
+    for tag in [format!("v{version}"), version.to_string()] {
+        let api_url = format!("https://api.github.com/repos/{repo}/releases/tags/{tag}");
+        let resp = get(&api_url)?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            continue;
+        }
+        if !resp.status().is_success() {
+            bail!(
+                "api request failed with status: {:?} - for: {:?}",
+                resp.status(),
+                api_url
+            )
+        }
+        let json = resp.json::<serde_json::Value>()?;
+        return Ok(json["assets"]
+            .as_array()
+            .map(|assets| {
+                assets
+                    .iter()
+                    .filter_map(|a| {
+                        Some((
+                            a["name"].as_str()?.to_string(),
+                            a["browser_download_url"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default());
+    }
+    bail!("no release of {repo} is tagged v{version} or {version}")
+}
+
 #[cfg(test)]
 mod tests {
+    #[cfg(target_os = "macos")]
     use super::*;
 
     #[test]
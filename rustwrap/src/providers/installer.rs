@@ -0,0 +1,494 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    archive,
+    console::style,
+    data::{one_per_arch, Libc, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+const INSTALL_SH: &str = "install.sh";
+const INSTALL_PS1: &str = "install.ps1";
+
+#[derive(Deserialize, Default)]
+pub struct InstallerOpts {
+    pub name: String,
+    pub bin: Option<String>,
+    /// verify downloads against the sha256 of the archives being released
+    #[serde(default)]
+    pub checksums: bool,
+}
+
+impl InstallerOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    /// env var overriding the install folder, e.g. `RECON_INSTALL`
+    fn install_env(&self) -> String {
+        format!("{}_INSTALL", self.name.to_uppercase().replace('-', "_"))
+    }
+}
+
+struct Release {
+    slug: String,
+    arch: String,
+    url: String,
+    format: &'static str,
+    sha: Option<String>,
+}
+
+/// archive format as told by a file name, `bin` for a raw binary
+fn archive_format(file_name: &str) -> &'static str {
+    let name = file_name.to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        "tar.gz"
+    } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+        "tar.xz"
+    } else if name.ends_with(".tar.bz2") || name.ends_with(".tbz") {
+        "tar.bz2"
+    } else if name.ends_with(".zip") {
+        "zip"
+    } else {
+        "bin"
+    }
+}
+
+fn release(target: &Target, version_var: &str, opts: &InstallerOpts) -> Result<Release> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let sha = if opts.checksums {
+        Some(archive::sha256(Path::new(fname))?)
+    } else {
+        None
+    };
+    // the downloaded file tells its format by content, urls do not have to
+    let format = match archive::archive_name(Path::new(fname), fname)? {
+        Some(name) => archive_format(&name),
+        None => "bin",
+    };
+    Ok(Release {
+        slug: target.tuple_slug(),
+        arch: target.arch.to_string(),
+        url: target.url(version_var),
+        format,
+        sha,
+    })
+}
+
+fn install_sh(version: &str, releases: &[Release], opts: &InstallerOpts) -> String {
+    let name = &opts.name;
+    let bin = opts.bin_name();
+    let install_env = opts.install_env();
+    let mut out = String::new();
+    let _ = writeln!(out, "#!/bin/sh");
+    let _ = writeln!(out, "# generated by rustwrap for {name} {version}");
+    let _ = writeln!(out, "# usage: install.sh [version]");
+    let _ = writeln!(out, "set -e");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "name=\"{name}\"");
+    let _ = writeln!(out, "bin_name=\"{bin}\"");
+    let _ = writeln!(out, "version=\"${{1:-{version}}}\"");
+    let _ = writeln!(out, "install=\"${{{install_env}:-$HOME/.{name}-bin}}\"");
+    let _ = writeln!(out);
+    out.push_str(
+        r#"case "$(uname -s)" in
+Linux) platform="linux" ;;
+Darwin) platform="darwin" ;;
+FreeBSD) platform="freebsd" ;;
+NetBSD) platform="netbsd" ;;
+OpenBSD) platform="openbsd" ;;
+SunOS) platform="sunos" ;;
+*) echo "unsupported platform: $(uname -s)" >&2 && exit 1 ;;
+esac
+if [ "$platform" = "linux" ] && [ "$(uname -o 2>/dev/null)" = "Android" ]; then
+	platform="android"
+fi
+case "$(uname -m)" in
+x86_64 | amd64 | i86pc) arch="x64" ;;
+aarch64 | arm64) arch="arm64" ;;
+i386 | i486 | i586 | i686) arch="ia32" ;;
+armv7* | armv8l) arch="arm" ;;
+riscv64) arch="riscv64" ;;
+s390x) arch="s390x" ;;
+ppc64le) arch="ppc64" ;;
+loongarch64) arch="loong64" ;;
+*) echo "unsupported architecture: $(uname -m)" >&2 && exit 1 ;;
+esac
+libc=""
+if [ "$platform" = "linux" ]; then
+	if ldd --version 2>&1 | grep -qi musl || ls /lib/ld-musl-* >/dev/null 2>&1; then
+		libc="musl"
+	else
+		libc="gnu"
+	fi
+fi
+
+# a release for this libc, then one that does not tell, then a static musl one
+url=""
+sha256=""
+for slug in "$platform-$arch-$libc" "$platform-$arch" "$platform-$arch-musl"; do
+	case "$slug" in
+"#,
+    );
+    for r in releases {
+        let _ = writeln!(out, "	{})", r.slug);
+        let _ = writeln!(out, "		url=\"{}\"", r.url);
+        let _ = writeln!(out, "		format=\"{}\"", r.format);
+        if let Some(sha) = &r.sha {
+            let _ = writeln!(out, "		sha256=\"{sha}\"");
+        }
+        let _ = writeln!(out, "		;;");
+    }
+    let _ = writeln!(out, "	esac");
+    let _ = writeln!(out, "	[ -n \"$url\" ] && break");
+    let _ = writeln!(out, "done");
+    let _ = writeln!(out, "if [ -z \"$url\" ]; then");
+    let _ = writeln!(
+        out,
+        "	echo \"$name has no release for $platform-$arch\" >&2 && exit 1"
+    );
+    let _ = writeln!(out, "fi");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "tmp=\"$(mktemp -d)\"");
+    let _ = writeln!(out, "trap 'rm -rf \"$tmp\"' EXIT");
+    let _ = writeln!(out, "download=\"$tmp/download\"");
+    let _ = writeln!(
+        out,
+        "curl --fail --location --progress-bar --output \"$download\" \"$url\""
+    );
+    let _ = writeln!(out);
+    // checksums are only known for the version being released
+    let _ = writeln!(
+        out,
+        "if [ -n \"$sha256\" ] && [ \"$version\" = \"{version}\" ]; then"
+    );
+    out.push_str(
+        r#"	if command -v sha256sum >/dev/null; then
+		actual="$(sha256sum "$download" | cut -d ' ' -f 1)"
+	else
+		actual="$(shasum -a 256 "$download" | cut -d ' ' -f 1)"
+	fi
+	if [ "$actual" != "$sha256" ]; then
+		echo "checksum mismatch for $url: expected $sha256, got $actual" >&2 && exit 1
+	fi
+fi
+
+case "$format" in
+tar.gz) tar xzf "$download" -C "$tmp" ;;
+tar.xz) tar xJf "$download" -C "$tmp" ;;
+tar.bz2) tar xjf "$download" -C "$tmp" ;;
+zip) unzip -q "$download" -d "$tmp" ;;
+*) mv "$download" "$tmp/$bin_name" ;;
+esac
+
+found="$(find "$tmp" -type f -name "$bin_name" | head -n 1)"
+if [ -z "$found" ]; then
+	echo "'$bin_name' was not found in $url" >&2 && exit 1
+fi
+mkdir -p "$install"
+exe="$install/$bin_name"
+mv "$found" "$exe"
+chmod +x "$exe"
+
+echo "$name $version was installed successfully to $exe"
+if command -v "$bin_name" >/dev/null; then
+	echo "Run '$bin_name --help' to get started"
+else
+	case $SHELL in
+	*/zsh) shell_profile=".zshrc" ;;
+	*) shell_profile=".bashrc" ;;
+	esac
+	echo "Manually add the directory to your \$HOME/$shell_profile (or similar)"
+"#,
+    );
+    let _ = writeln!(out, "\techo \"  export {install_env}=\\\"$install\\\"\"");
+    let _ = writeln!(
+        out,
+        "\techo \"  export PATH=\\\"\\${install_env}:\\$PATH\\\"\""
+    );
+    let _ = writeln!(out, "\techo \"Run '$exe --help' to get started\"");
+    let _ = writeln!(out, "fi");
+    out
+}
+
+fn install_ps1(version: &str, releases: &[Release], opts: &InstallerOpts) -> String {
+    let name = &opts.name;
+    let bin = format!("{}.exe", opts.bin_name());
+    let install_env = opts.install_env();
+    let mut out = String::new();
+    let _ = writeln!(out, "#!/usr/bin/env pwsh");
+    let _ = writeln!(out, "# generated by rustwrap for {name} {version}");
+    let _ = writeln!(out, "# usage: install.ps1 [version]");
+    let _ = writeln!(out, "$ErrorActionPreference = 'Stop'");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "$Name = \"{name}\"");
+    let _ = writeln!(out, "$BinName = \"{bin}\"");
+    let _ = writeln!(
+        out,
+        "$Version = if ($args.Length -eq 1) {{ $args.Get(0) }} else {{ \"{version}\" }}"
+    );
+    let _ = writeln!(
+        out,
+        "$BinDir = if ($env:{install_env}) {{ $env:{install_env} }} else {{ \"$Home\\.{name}-bin\" }}"
+    );
+    let _ = writeln!(out);
+    out.push_str(
+        r#"$Arch = switch ($env:PROCESSOR_ARCHITECTURE) {
+  "AMD64" { "x64" }
+  "ARM64" { "arm64" }
+  "x86" { "ia32" }
+  "ARM" { "arm" }
+  default { throw "unsupported architecture: $env:PROCESSOR_ARCHITECTURE" }
+}
+
+$Sha256 = ""
+switch ($Arch) {
+"#,
+    );
+    for r in releases {
+        let _ = writeln!(out, "  \"{}\" {{", r.arch);
+        let _ = writeln!(out, "    $Url = \"{}\"", r.url);
+        let _ = writeln!(out, "    $Format = \"{}\"", r.format);
+        if let Some(sha) = &r.sha {
+            let _ = writeln!(out, "    $Sha256 = \"{sha}\"");
+        }
+        let _ = writeln!(out, "  }}");
+    }
+    let _ = writeln!(
+        out,
+        "  default {{ throw \"$Name has no release for win32-$Arch\" }}"
+    );
+    let _ = writeln!(out, "}}");
+    let _ = writeln!(out);
+    out.push_str(
+        r#"# GitHub requires TLS 1.2
+[Net.ServicePointManager]::SecurityProtocol = [Net.SecurityProtocolType]::Tls12
+
+$Tmp = Join-Path ([IO.Path]::GetTempPath()) ([Guid]::NewGuid())
+New-Item $Tmp -ItemType Directory | Out-Null
+$Download = "$Tmp\download"
+curl.exe --fail --location --progress-bar --output $Download $Url
+
+"#,
+    );
+    let _ = writeln!(out, "if ($Sha256 -and $Version -eq \"{version}\") {{");
+    out.push_str(
+        r#"  $Actual = (Get-FileHash $Download -Algorithm SHA256).Hash.ToLower()
+  if ($Actual -ne $Sha256) {
+    throw "checksum mismatch for ${Url}: expected $Sha256, got $Actual"
+  }
+}
+
+if ($Format -eq "bin") {
+  Move-Item $Download "$Tmp\$BinName"
+} else {
+  tar.exe xf $Download -C $Tmp
+}
+
+$Found = Get-ChildItem -Path $Tmp -Recurse -File -Filter $BinName | Select-Object -First 1
+if (!$Found) {
+  throw "'$BinName' was not found in $Url"
+}
+if (!(Test-Path $BinDir)) {
+  New-Item $BinDir -ItemType Directory | Out-Null
+}
+$Exe = "$BinDir\$BinName"
+Move-Item -Force $Found.FullName $Exe
+Remove-Item -Recurse $Tmp
+
+$User = [EnvironmentVariableTarget]::User
+$Path = [Environment]::GetEnvironmentVariable('Path', $User)
+if (!(";$Path;".ToLower() -like "*;$BinDir;*".ToLower())) {
+  [Environment]::SetEnvironmentVariable('Path', "$Path;$BinDir", $User)
+  $Env:Path += ";$BinDir"
+}
+
+Write-Output "$Name $Version was installed successfully to $Exe"
+Write-Output "Run with '--help' to get started"
+"#,
+    );
+    out
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &InstallerOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "installer");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("installer").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let unix = targets
+        .iter()
+        .filter(|t| !matches!(t.platform, Platform::Win32 | Platform::Unknown))
+        .map(|t| release(t, "${version}", opts))
+        .collect::<Result<Vec<_>>>()?;
+    // install.ps1 picks a release by arch alone
+    let windows = one_per_arch(
+        targets.iter().filter(|t| t.platform == Platform::Win32),
+        Libc::Gnu,
+    )
+    .into_iter()
+    .map(|t| release(t, "${Version}", opts))
+    .collect::<Result<Vec<_>>>()?;
+    if unix.is_empty() && windows.is_empty() {
+        bail!("no targets available");
+    }
+
+    if !unix.is_empty() {
+        let script = install_sh(version, &unix, opts);
+        tracing::info!(script, "rendered install.sh");
+        fs::write(out_dir.join(INSTALL_SH), script)?;
+        session
+            .console
+            .say(&format!("   {} {}", style("script").yellow(), INSTALL_SH));
+    }
+    if !windows.is_empty() {
+        fs::write(
+            out_dir.join(INSTALL_PS1),
+            install_ps1(version, &windows, opts),
+        )?;
+        session
+            .console
+            .say(&format!("   {} {}", style("script").yellow(), INSTALL_PS1));
+    }
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+impl Provider for InstallerOpts {
+    fn name(&self) -> &'static str {
+        "installer"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "installer"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        console::MemConsole,
+        data::{Architecture, Config},
+    };
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        let out = Path::new("out/installer_test_generate");
+        let fixtures = fs::canonicalize("fixtures/archives").unwrap();
+        let target = |platform: Platform, arch: Architecture, fname: &str| Target {
+            platform,
+            arch,
+            url_template: format!("file://{}/{fname}", fixtures.display()),
+            archive: Some(format!("fixtures/archives/{fname}")),
+            ..Default::default()
+        };
+        generate(
+            &mut session,
+            out,
+            "1.0.1",
+            &[
+                target(
+                    Platform::Linux,
+                    Architecture::X64,
+                    "recon-x86_64-linux.tar.gz",
+                ),
+                target(
+                    Platform::Linux,
+                    Architecture::ARM64,
+                    "recon-aarch64-linux.tar.gz",
+                ),
+                target(
+                    Platform::Darwin,
+                    Architecture::X64,
+                    "recon-x86_64-macos.tar.gz",
+                ),
+                target(
+                    Platform::Darwin,
+                    Architecture::ARM64,
+                    "recon-aarch64-macos.tar.gz",
+                ),
+                // served without an extension, a zip by content
+                Target {
+                    url_template: "https://example.com/recon/latest/windows".to_string(),
+                    ..target(
+                        Platform::Win32,
+                        Architecture::X64,
+                        "recon-x86_64-windows.zip",
+                    )
+                },
+                target(
+                    Platform::Win32,
+                    Architecture::X64,
+                    "recon-x86_64-windows.zip",
+                ),
+            ],
+            &InstallerOpts {
+                name: "recon".to_string(),
+                checksums: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let scripts = out.join("recon-1.0.1/installer");
+        let sha = archive::sha256(Path::new("fixtures/archives/recon-x86_64-windows.zip")).unwrap();
+        let ps1 = fs::read_to_string(scripts.join(INSTALL_PS1)).unwrap();
+        assert!(ps1.contains(&format!(
+            "  \"x64\" {{\n    $Url = \"https://example.com/recon/latest/windows\"\n    $Format = \"zip\"\n    $Sha256 = \"{sha}\""
+        )));
+        assert_eq!(ps1.matches("\"x64\" {").count(), 1);
+        assert!(!ps1.contains("linux"));
+
+        // install.sh downloads from the fixtures through file:// urls
+        if cfg!(unix) {
+            let install = fs::canonicalize(out).unwrap().join("bin");
+            let stdout = duct::cmd!("sh", scripts.join(INSTALL_SH))
+                .env("RECON_INSTALL", &install)
+                .stderr_null()
+                .read()
+                .unwrap();
+            assert!(stdout.contains("recon 1.0.1 was installed successfully"));
+            assert_eq!(
+                duct::cmd!(install.join("recon")).read().unwrap(),
+                "recon 1.0.1"
+            );
+        }
+    }
+}
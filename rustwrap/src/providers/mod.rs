@@ -1,3 +1,253 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+
+use crate::data::{Config, Session, Target};
+
+pub mod aur;
 pub mod brew;
+pub mod chocolatey;
+pub mod deb;
 pub mod github;
+pub mod installer;
+pub mod nix;
 pub mod npm;
+pub mod oci;
+pub mod pypi;
+pub mod rpm;
+pub mod scoop;
+pub mod winget;
+
+/// A package registry rustwrap can wrap releases for.
+///
+/// Providers are deserialized from their section in `rustwrap.yaml` (see [`Registry`]).
+/// `generate` only writes to `out_dir`, and `publish` only reads back what `generate`
+/// wrote there, so the two can run in separate processes.
+pub trait Provider {
+    /// Name used in output and messages
+    fn name(&self) -> &'static str;
+
+    /// Check the configuration, before anything is downloaded
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the configuration is invalid
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether `publish` pushes anything, as opposed to only generating files
+    fn publishes(&self) -> bool {
+        false
+    }
+
+    /// Latest version already published, `None` if unknown or never published
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the registry could not be queried
+    fn latest_version(&self) -> Result<Option<semver::Version>> {
+        Ok(None)
+    }
+
+    /// Folder under `out_dir` that `generate` writes `version` into, which the release
+    /// checksums cover. `None` leaves the output out of the checksums.
+    fn output_dir(&self, _out_dir: &Path, _version: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// Render packages for `version` from downloaded `targets` into `out_dir`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if rendering or IO failed
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()>;
+
+    /// What `publish` would push from `out_dir` for `version`, without pushing it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if generated output could not be read
+    fn plan(&self, _out_dir: &Path, _version: &str) -> Result<Vec<Action>> {
+        Ok(vec![])
+    }
+
+    /// Check `out_dir` has everything `publish` needs for `version`, without network access
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if generated output is missing or unreadable
+    fn verify(&self, _out_dir: &Path, _version: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Push what `generate` left in `out_dir` for `version`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if pushing to the registry failed
+    fn publish(&self, _session: &mut Session<'_>, _out_dir: &Path, _version: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A single push `publish` would make, as listed by dry runs
+#[derive(Debug, PartialEq, Eq)]
+pub struct Action {
+    /// What gets published, e.g. a package name and version or a file
+    pub artifact: String,
+    /// Where it gets published, e.g. a registry or a repo
+    pub destination: String,
+    /// Unified diff against what is currently published, for files that get replaced
+    pub diff: Option<String>,
+}
+
+impl Action {
+    /// An action without a diff
+    pub fn new(artifact: impl Into<String>, destination: impl Into<String>) -> Self {
+        Self {
+            artifact: artifact.into(),
+            destination: destination.into(),
+            diff: None,
+        }
+    }
+}
+
+/// Unified diff of a file's `current` content (if any) against `new`, `None` when unchanged
+pub fn diff(fname: &str, current: Option<&str>, new: &str) -> Option<String> {
+    let current = current.unwrap_or_default();
+    if current == new {
+        return None;
+    }
+    Some(
+        similar::TextDiff::from_lines(current, new)
+            .unified_diff()
+            .header(&format!("a/{fname}"), &format!("b/{fname}"))
+            .to_string(),
+    )
+}
+
+type Factory = Box<dyn Fn(serde_yaml::Value) -> Result<Box<dyn Provider>>>;
+
+/// Providers known by config section name
+pub struct Registry {
+    factories: HashMap<String, Factory>,
+}
+
+impl Default for Registry {
+    /// A registry with all built-in providers
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register::<aur::AurOpts>("aur")
+            .register::<brew::BrewOpts>("brew")
+            .register::<chocolatey::ChocolateyOpts>("chocolatey")
+            .register::<deb::DebOpts>("deb")
+            .register::<installer::InstallerOpts>("installer")
+            .register::<nix::NixOpts>("nix")
+            .register::<npm::NpmOpts>("npm")
+            .register::<oci::OciOpts>("oci")
+            .register::<pypi::PypiOpts>("pypi")
+            .register::<rpm::RpmOpts>("rpm")
+            .register::<scoop::ScoopOpts>("scoop")
+            .register::<winget::WingetOpts>("winget");
+        registry
+    }
+}
+
+impl Registry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register provider `P`, configured by the `section` key in `rustwrap.yaml`
+    pub fn register<P>(&mut self, section: &str) -> &mut Self
+    where
+        P: Provider + DeserializeOwned + 'static,
+    {
+        self.factories.insert(
+            section.to_string(),
+            Box::new(|value| Ok(Box::new(serde_yaml::from_value::<P>(value)?))),
+        );
+        self
+    }
+
+    /// Build the providers configured in `config`, in the order they appear
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a section has no provider, or does not parse
+    pub fn load(&self, config: &Config) -> Result<Vec<Box<dyn Provider>>> {
+        config
+            .providers
+            .iter()
+            .map(|(key, value)| {
+                let section = key
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("invalid config section: {key:?}"))?;
+                let factory = self
+                    .factories
+                    .get(section)
+                    .ok_or_else(|| anyhow::anyhow!("unknown provider: '{section}'"))?;
+                factory(value.clone()).with_context(|| format!("invalid '{section}' config"))
+            })
+            .collect()
+    }
+}
+
+/// Where a provider keeps its output for a release: `<out_dir>/<name>-<version>/<provider>`
+pub fn package_dir(out_dir: &Path, name: &str, version: &str, provider: &str) -> PathBuf {
+    out_dir.join(format!("{name}-{version}")).join(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let config: Config = serde_yaml::from_str(
+            "
+repo: rusty-ferris-club/recon
+targets: []
+nix:
+  name: recon
+installer:
+  name: recon
+",
+        )
+        .unwrap();
+        let names = Registry::default()
+            .load(&config)
+            .unwrap()
+            .iter()
+            .map(|p| p.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["nix", "installer"]);
+
+        let config: Config = serde_yaml::from_str("targets: []\nsnap:\n  name: recon\n").unwrap();
+        assert!(Registry::default().load(&config).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("recon.rb", Some("a\n"), "a\n"), None);
+        assert_eq!(
+            diff("recon.rb", Some("a\nb\n"), "a\nc\n").unwrap(),
+            "--- a/recon.rb\n+++ b/recon.rb\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+}
@@ -0,0 +1,313 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    archive,
+    console::style,
+    data::{one_per_arch, Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+const DEFAULT_NIX: &str = "default.nix";
+const FLAKE_NIX: &str = "flake.nix";
+
+#[derive(Deserialize, Default)]
+pub struct NixOpts {
+    pub name: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub bin: Option<String>,
+    #[serde(default)]
+    pub flake: bool,
+}
+
+impl NixOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+}
+
+struct Source {
+    system: String,
+    url: String,
+    hash: String,
+    dir: Option<String>,
+}
+
+/// nix system string for a target, `None` for platforms nix does not run on
+fn nix_system(target: &Target) -> Option<String> {
+    let arch = match target.arch {
+        Architecture::X64 => "x86_64",
+        Architecture::ARM64 => "aarch64",
+        Architecture::IA32 => "i686",
+        Architecture::ARM => "armv7l",
+        Architecture::RISCV64 => "riscv64",
+        Architecture::S390X => "s390x",
+        Architecture::PPC64 => "powerpc64le",
+        Architecture::LOONG64 => "loongarch64",
+    };
+    let os = match target.platform {
+        Platform::Linux => "linux",
+        Platform::Darwin => "darwin",
+        Platform::FreeBSD => "freebsd",
+        Platform::NetBSD => "netbsd",
+        Platform::OpenBSD => "openbsd",
+        Platform::Win32 | Platform::SunOS | Platform::Android | Platform::Unknown => return None,
+    };
+    Some(format!("{arch}-{os}"))
+}
+
+/// quote a nix string, escaping interpolation
+fn nix_str(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("${", "\\${")
+    )
+}
+
+fn default_nix(version: &str, sources: &[Source], opts: &NixOpts) -> String {
+    let bin = opts.bin_name();
+    let mut out = String::new();
+    let _ = writeln!(out, "# generated by rustwrap");
+    let _ = writeln!(out, "{{ pkgs ? import <nixpkgs> {{ }} }}:");
+    let _ = writeln!(out, "let");
+    let _ = writeln!(out, "  sources = {{");
+    for s in sources {
+        let _ = writeln!(out, "    {} = {{", nix_str(&s.system));
+        let _ = writeln!(out, "      url = {};", nix_str(&s.url));
+        let _ = writeln!(out, "      hash = {};", nix_str(&s.hash));
+        let _ = writeln!(
+            out,
+            "      dir = {};",
+            nix_str(s.dir.as_deref().unwrap_or("."))
+        );
+        let _ = writeln!(out, "    }};");
+    }
+    let _ = writeln!(out, "  }};");
+    let _ = writeln!(out, "  system = pkgs.stdenv.hostPlatform.system;");
+    let _ = writeln!(
+        out,
+        "  source = sources.${{system}} or (throw \"{}: unsupported system ${{system}}\");",
+        opts.name
+    );
+    let _ = writeln!(out, "in");
+    let _ = writeln!(out, "pkgs.stdenv.mkDerivation {{");
+    let _ = writeln!(out, "  pname = {};", nix_str(&opts.name));
+    let _ = writeln!(out, "  version = {};", nix_str(version));
+    let _ = writeln!(
+        out,
+        "  src = pkgs.fetchurl {{ inherit (source) url hash; }};"
+    );
+    let _ = writeln!(out, "  sourceRoot = \".\";");
+    let _ = writeln!(
+        out,
+        "  nativeBuildInputs = [ pkgs.unzip ] ++ pkgs.lib.optionals pkgs.stdenv.isLinux [ pkgs.autoPatchelfHook ];"
+    );
+    let _ = writeln!(out, "  installPhase = ''");
+    let _ = writeln!(out, "    runHook preInstall");
+    let _ = writeln!(
+        out,
+        "    install -Dm755 ${{source.dir}}/{bin} $out/bin/{bin}"
+    );
+    let _ = writeln!(out, "    runHook postInstall");
+    let _ = writeln!(out, "  '';");
+    let _ = writeln!(out, "  meta = {{");
+    if let Some(description) = &opts.description {
+        let _ = writeln!(out, "    description = {};", nix_str(description));
+    }
+    if let Some(homepage) = &opts.homepage {
+        let _ = writeln!(out, "    homepage = {};", nix_str(homepage));
+    }
+    if let Some(license) = &opts.license {
+        let _ = writeln!(
+            out,
+            "    license = pkgs.lib.getLicenseFromSpdxId {};",
+            nix_str(license)
+        );
+    }
+    let _ = writeln!(out, "    mainProgram = {};", nix_str(&bin));
+    let _ = writeln!(out, "    platforms = builtins.attrNames sources;");
+    let _ = writeln!(out, "  }};");
+    let _ = writeln!(out, "}}");
+    out
+}
+
+fn flake_nix(sources: &[Source], opts: &NixOpts) -> String {
+    let systems = sources
+        .iter()
+        .map(|s| nix_str(&s.system))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut out = String::new();
+    let _ = writeln!(out, "# generated by rustwrap");
+    let _ = writeln!(out, "{{");
+    let _ = writeln!(
+        out,
+        "  description = {};",
+        nix_str(opts.description.as_ref().unwrap_or(&opts.name))
+    );
+    let _ = writeln!(
+        out,
+        "  inputs.nixpkgs.url = \"github:NixOS/nixpkgs/nixos-unstable\";"
+    );
+    let _ = writeln!(out, "  outputs = {{ self, nixpkgs }}:");
+    let _ = writeln!(out, "    let");
+    let _ = writeln!(
+        out,
+        "      forAllSystems = nixpkgs.lib.genAttrs [ {systems} ];"
+    );
+    let _ = writeln!(out, "    in");
+    let _ = writeln!(out, "    {{");
+    let _ = writeln!(out, "      packages = forAllSystems (system: {{");
+    let _ = writeln!(
+        out,
+        "        default = import ./{DEFAULT_NIX} {{ pkgs = nixpkgs.legacyPackages.${{system}}; }};"
+    );
+    let _ = writeln!(out, "      }});");
+    let _ = writeln!(out, "    }};");
+    let _ = writeln!(out, "}}");
+    out
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &NixOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "nix");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("nix").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let sources = one_per_arch(targets, Libc::Gnu)
+        .into_iter()
+        .filter_map(|t| nix_system(t).map(|system| (system, t)))
+        .map(|(system, t)| {
+            let fname = t
+                .archive
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", t))?;
+            Ok(Source {
+                system,
+                url: t.url(version),
+                hash: archive::sha256_sri(Path::new(fname))?,
+                dir: archive::root_dir(Path::new(fname))?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if sources.is_empty() {
+        bail!("no targets available");
+    }
+
+    let default_nix = default_nix(version, &sources, opts);
+    tracing::info!(default_nix, "rendered derivation");
+    fs::write(out_dir.join(DEFAULT_NIX), default_nix)?;
+    if opts.flake {
+        fs::write(out_dir.join(FLAKE_NIX), flake_nix(&sources, opts))?;
+    }
+    session.console.say(&format!(
+        "{} saved derivation to '{}'",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta(),
+    ));
+    Ok(())
+}
+
+impl Provider for NixOpts {
+    fn name(&self) -> &'static str {
+        "nix"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "nix"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/nix_test_generate"),
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::X64,
+                    url_template: "https://example.com/v__VERSION__/recon-x86_64-linux.tar.gz"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Darwin,
+                    arch: Architecture::ARM64,
+                    url_template: "https://example.com/v__VERSION__/recon-aarch64-macos.tar.gz"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-aarch64-macos.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::X64,
+                    ..Default::default()
+                },
+            ],
+            &NixOpts {
+                name: "recon".to_string(),
+                description: Some("recon \"tool\"".to_string()),
+                license: Some("Apache-2.0".to_string()),
+                flake: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let out = Path::new("out/nix_test_generate/recon-1.0.1/nix");
+        let default_nix = fs::read_to_string(out.join(DEFAULT_NIX)).unwrap();
+        let sri =
+            archive::sha256_sri(Path::new("fixtures/archives/recon-aarch64-macos.tar.gz")).unwrap();
+        assert!(default_nix.contains("\"x86_64-linux\" = {"));
+        assert!(default_nix.contains(&format!("\"aarch64-darwin\" = {{\n      url = \"https://example.com/v1.0.1/recon-aarch64-macos.tar.gz\";\n      hash = \"{sri}\";\n      dir = \"recon-aarch64-macos\";")));
+        assert!(!default_nix.contains("windows"));
+        assert!(default_nix.contains("description = \"recon \\\"tool\\\"\";"));
+
+        let flake_nix = fs::read_to_string(out.join(FLAKE_NIX)).unwrap();
+        assert!(flake_nix.contains("genAttrs [ \"x86_64-linux\" \"aarch64-darwin\" ]"));
+    }
+}
@@ -5,13 +5,19 @@ use decompress::{decompress, ExtractOpts};
 use itertools::Itertools;
 use serde::Deserialize;
 
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use fs_err as fs;
 use serde_json::json;
 
-use crate::data::{Session, Target};
+use crate::{
+    data::{Libc, Platform, Session, Target},
+    providers::{package_dir, Action, Provider},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct PackageInfo {
@@ -42,22 +48,40 @@ const POSTINSTALL: &str = include_str!("static/npm/postinstall.js");
 const PACKAGE_JSON: &str = "package.json";
 const POSTINSTALL_JS: &str = "postinstall.js";
 const INFO_JSON: &str = "info.json";
+const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
 
-pub fn latest(opts: &NpmOpts) -> Result<semver::Version> {
+/// Version of the root package in the registry, `None` if it was never published
+pub fn latest(opts: &NpmOpts) -> Result<Option<semver::Version>> {
     #[cfg(not(target_os = "windows"))]
-    let out = duct::cmd!("npm", "view", opts.root_package_name(), "version").read()?;
+    let cmd = duct::cmd!("npm", "view", opts.root_package_name(), "version");
     #[cfg(target_os = "windows")]
-    let out = duct::cmd!(
+    let cmd = duct::cmd!(
         "cmd",
         "/C",
         "npm",
         "view",
         opts.root_package_name(),
         "version"
+    );
+    let out = cmd.stdout_capture().stderr_capture().unchecked().run()?;
+    view_version(
+        out.status.success(),
+        &String::from_utf8_lossy(&out.stdout),
+        &String::from_utf8_lossy(&out.stderr),
     )
-    .read()?;
+}
 
-    semver::Version::parse(&out).context("cannot parse version")
+/// Version out of `npm view`, which fails with `E404` for a package that does not exist
+fn view_version(success: bool, stdout: &str, stderr: &str) -> Result<Option<semver::Version>> {
+    if !success {
+        if stderr.contains("E404") {
+            return Ok(None);
+        }
+        bail!("npm view failed: {}", stderr.trim());
+    }
+    semver::Version::parse(stdout.trim())
+        .map(Some)
+        .context("cannot parse version")
 }
 
 fn subpkg_name(target: &Target, opts: &NpmOpts) -> String {
@@ -92,10 +116,29 @@ fn edit_files(hash: &mut serde_json::Map<String, serde_json::Value>, entries: &[
     );
 }
 
+/// npm's name for the libc of a linux target, as matched by the `libc` field of a package
+fn npm_libc(target: &Target) -> Option<&'static str> {
+    (target.platform == Platform::Linux).then(|| match target.libc_or_gnu() {
+        Libc::Gnu => "glibc",
+        Libc::Musl => "musl",
+    })
+}
+
+/// Whether another target runs on the same platform and arch with a different libc,
+/// so npm has to tell their packages apart
+fn libc_split(target: &Target, targets: &[Target]) -> bool {
+    targets.iter().any(|t| {
+        t.platform == target.platform
+            && t.arch == target.arch
+            && t.libc_or_gnu() != target.libc_or_gnu()
+    })
+}
+
 fn edit_subpkg(
     pkg: &serde_json::Value,
     version: &str,
     target: &Target,
+    libc: Option<&str>,
     opts: &NpmOpts,
 ) -> serde_json::Value {
     let mut new = pkg.clone();
@@ -104,6 +147,9 @@ fn edit_subpkg(
     res.insert("version".to_string(), json!(version.to_string()));
     res.insert("os".to_string(), json!([target.platform.to_string()]));
     res.insert("cpu".to_string(), json!([target.arch.to_string()]));
+    if let Some(libc) = libc {
+        res.insert("libc".to_string(), json!([libc]));
+    }
 
     let bin_name = target.bin_name(&opts.shim_name()).to_string();
     edit_files(res, &[bin_name.as_str()]);
@@ -163,14 +209,14 @@ fn copy_readme(pkg_path: &Path, pkg: &PackageInfo) -> Result<()> {
 }
 
 #[tracing::instrument(level = "trace", skip(session), err)]
-pub fn publish(
+pub fn generate(
     session: &mut Session<'_>,
     out_dir: &Path,
     version: &str,
     targets: &[Target],
     opts: &NpmOpts,
 ) -> Result<()> {
-    let out_dir = out_dir.join(format!("{}-{version}", opts.name)).join("npm");
+    let out_dir = package_dir(out_dir, &opts.name, version, "npm");
     let prefix = format!("{} {}", crate::console::PKG, style("npm").green());
     session.console.say(&format!(
         "{} generating into {}",
@@ -194,7 +240,8 @@ pub fn publish(
             fs::create_dir_all(&subpkg_path)?;
         }
 
-        let subpkg = edit_subpkg(&subpkg_json, version, target, opts);
+        let libc = npm_libc(target).filter(|_| libc_split(target, targets));
+        let subpkg = edit_subpkg(&subpkg_json, version, target, libc, opts);
         serde_json::to_writer_pretty(fs::File::create(subpkg_path.join(PACKAGE_JSON))?, &subpkg)?;
 
         // copy readme
@@ -213,15 +260,6 @@ pub fn publish(
             style("subpackage").yellow(),
             &pkg_name,
         ));
-        if opts.publish {
-            let out = duct::cmd!("npm", "publish").dir(&subpkg_path).read()?;
-            session.console.say(&format!(
-                "   {} {} published:\n{}",
-                style("subpackage").yellow(),
-                &pkg_name,
-                &out,
-            ));
-        }
         tracing::trace!("npm: done");
     }
 
@@ -271,6 +309,7 @@ pub fn publish(
             "platforms": &targets.iter().map(|t| json!({
               "platform": t.platform,
               "arch": t.arch,
+              "libc": npm_libc(t),
               "bin":format!("{}/{}",  subpkg_name(t, opts),  t.bin_name(&opts.shim_name()))
             })).collect::<Vec<_>>(),
             "name": opts.name,
@@ -278,24 +317,129 @@ pub fn publish(
     )?;
     tracing::trace!("npm: wrote info.json.");
 
-    if opts.publish {
-        let out = duct::cmd!("npm", "publish").dir(&rootpkg_path).read()?;
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+/// Generated subpackage folders, as listed in the root package's `info.json`
+fn generated_subpkgs(out_dir: &Path, opts: &NpmOpts) -> Result<Vec<String>> {
+    let info: serde_json::Value =
+        serde_json::from_reader(fs::File::open(out_dir.join(&opts.name).join(INFO_JSON))?)?;
+    Ok(info["platforms"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p["bin"].as_str())
+        .filter_map(|bin| {
+            bin.rsplit_once('/')
+                .map(|(pkg_name, _)| pkg_name.to_string())
+        })
+        .collect::<Vec<_>>())
+}
+
+/// `name@version` of the generated package in `pkg_path`, and the registry it publishes to
+fn package_action(pkg_path: &Path) -> Result<Action> {
+    let pkg: serde_json::Value =
+        serde_json::from_reader(fs::File::open(pkg_path.join(PACKAGE_JSON))?)?;
+    Ok(Action::new(
+        format!(
+            "{}@{}",
+            pkg["name"].as_str().unwrap_or_default(),
+            pkg["version"].as_str().unwrap_or_default()
+        ),
+        pkg.pointer("/publishConfig/registry")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(DEFAULT_REGISTRY),
+    ))
+}
+
+pub fn plan(out_dir: &Path, version: &str, opts: &NpmOpts) -> Result<Vec<Action>> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "npm");
+    generated_subpkgs(&out_dir, opts)?
+        .iter()
+        .chain(std::iter::once(&opts.name))
+        .map(|pkg_name| package_action(&out_dir.join(pkg_name)))
+        .collect()
+}
+
+/// Publish generated subpackages, then the root package that depends on them
+#[tracing::instrument(level = "trace", skip(session), err)]
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &NpmOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "npm");
+    for pkg_name in generated_subpkgs(&out_dir, opts)? {
+        let out = duct::cmd!("npm", "publish")
+            .dir(out_dir.join(&pkg_name))
+            .read()?;
         session.console.say(&format!(
-            "   {}    {} published:\n{}",
-            style("package").yellow(),
-            &opts.name,
+            "   {} {} published:\n{}",
+            style("subpackage").yellow(),
+            &pkg_name,
             &out,
         ));
     }
-    session.console.say(&format!("{prefix} done."));
+
+    let out = duct::cmd!("npm", "publish")
+        .dir(out_dir.join(&opts.name))
+        .read()?;
+    session.console.say(&format!(
+        "   {}    {} published:\n{}",
+        style("package").yellow(),
+        &opts.name,
+        &out,
+    ));
     Ok(())
 }
 
+impl Provider for NpmOpts {
+    fn name(&self) -> &'static str {
+        "npm"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "npm"))
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn latest_version(&self) -> Result<Option<semver::Version>> {
+        latest(self)
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        plan(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         console::MemConsole,
-        data::{Architecture, Config, Platform},
+        data::{Architecture, Config},
     };
 
     use super::*;
@@ -312,6 +456,16 @@ mod tests {
             }
             .tuple_slug()
         );
+        assert_eq!(
+            "linux-x64-musl",
+            Target {
+                arch: Architecture::X64,
+                platform: Platform::Linux,
+                libc: Some(Libc::Musl),
+                ..Default::default()
+            }
+            .tuple_slug()
+        );
         assert_eq!(
             "recon",
             Target {
@@ -338,7 +492,7 @@ mod tests {
             config: &Config::default(),
             console: &mut MemConsole::default(),
         };
-        publish(
+        generate(
             &mut session,
             Path::new("out/test_generate"),
             "1.0.1",
@@ -375,10 +529,53 @@ mod tests {
     }
 
     #[test]
-    fn test_latest_version() {
-        let v = latest(&NpmOpts {
+    fn test_plan() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        let opts = NpmOpts {
+            org: "@recontools".to_owned(),
+            name: "recon".to_owned(),
+            publish: true,
+            bin: None,
+            root: PackageInfo {
+                name: None,
+                manifest: "fixtures/config/recon-root.json".to_owned(),
+                readme: None,
+            },
+            sub: PackageInfo {
+                name: None,
+                manifest: "fixtures/config/recon-sub.json".to_owned(),
+                readme: None,
+            },
+        };
+        let out = Path::new("out/npm_test_plan");
+        generate(
+            &mut session,
+            out,
+            "1.0.1",
+            &[Target {
+                platform: Platform::Linux,
+                arch: Architecture::X64,
+                ..Default::default()
+            }],
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            plan(out, "1.0.1", &opts).unwrap(),
+            vec![
+                Action::new("@recontools/recon-bin-linux-x64@1.0.1", DEFAULT_REGISTRY),
+                Action::new("recon@1.0.1", DEFAULT_REGISTRY),
+            ]
+        );
+    }
+
+    fn view_opts(name: &str) -> NpmOpts {
+        NpmOpts {
             org: "foo".to_string(),
-            name: "react".to_string(),
+            name: name.to_string(),
             publish: false,
             bin: None,
             root: PackageInfo {
@@ -391,8 +588,31 @@ mod tests {
                 manifest: String::new(),
                 readme: None,
             },
-        })
-        .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_latest_version() {
+        let v = latest(&view_opts("react")).unwrap().unwrap();
         assert!(v > semver::Version::parse("18.0.0").unwrap());
     }
+
+    #[test]
+    fn test_latest_version_unpublished() {
+        assert_eq!(
+            latest(&view_opts("rustwrap-never-published-3f9c1e")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_view_version() {
+        assert_eq!(
+            view_version(true, "18.2.0\n", "").unwrap(),
+            Some(semver::Version::new(18, 2, 0))
+        );
+        let not_found = "npm error code E404\nnpm error 404 Not Found - GET https://registry.npmjs.org/nope - Not found\n";
+        assert_eq!(view_version(false, "", not_found).unwrap(), None);
+        assert!(view_version(false, "", "npm error code ECONNREFUSED\n").is_err());
+    }
 }
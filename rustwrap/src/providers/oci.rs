@@ -0,0 +1,360 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use fs_err as fs;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Digest;
+
+use crate::{
+    archive::{self, Entry},
+    console::style,
+    data::{one_per_arch, Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+const MEDIA_TYPE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const MEDIA_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+const MEDIA_TYPE_LAYER: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+#[derive(Deserialize, Default)]
+pub struct OciOpts {
+    pub name: String,
+    pub image: Option<String>,
+    pub bin: Option<String>,
+    /// gzipped rootfs tarball per arch (`x64`, `arm64`) to use instead of `scratch`
+    pub base: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub archive: bool,
+}
+
+impl OciOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+}
+
+fn oci_arch(arch: &Architecture) -> &'static str {
+    match arch {
+        Architecture::X64 => "amd64",
+        Architecture::ARM64 => "arm64",
+        Architecture::IA32 => "386",
+        Architecture::ARM => "arm",
+        Architecture::RISCV64 => "riscv64",
+        Architecture::S390X => "s390x",
+        Architecture::PPC64 => "ppc64le",
+        Architecture::LOONG64 => "loong64",
+    }
+}
+
+/// image platform for an architecture, with a variant where the architecture needs one
+fn oci_platform(arch: &Architecture) -> serde_json::Value {
+    let mut platform = json!({ "architecture": oci_arch(arch), "os": "linux" });
+    if arch == &Architecture::ARM {
+        platform["variant"] = json!("v7");
+    }
+    platform
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", sha2::Sha256::digest(data))
+}
+
+/// content addressed blobs of an image layout, kept in memory until written out
+#[derive(Default)]
+struct Layout {
+    blobs: Vec<(String, Vec<u8>)>,
+}
+
+impl Layout {
+    fn add(&mut self, media_type: &str, data: Vec<u8>) -> serde_json::Value {
+        let descriptor = json!({
+            "mediaType": media_type,
+            "digest": digest(&data),
+            "size": data.len(),
+        });
+        let path = format!("blobs/sha256/{}", &digest(&data)["sha256:".len()..]);
+        if !self.blobs.iter().any(|(p, _)| p == &path) {
+            self.blobs.push((path, data));
+        }
+        descriptor
+    }
+
+    fn add_json(
+        &mut self,
+        media_type: &str,
+        value: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        Ok(self.add(media_type, serde_json::to_vec(value)?))
+    }
+}
+
+fn image(layout: &mut Layout, target: &Target, opts: &OciOpts) -> Result<serde_json::Value> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let bin_name = opts.bin_name();
+    let bin = archive::read_bin(Path::new(fname), &bin_name)?;
+    let bin_path = format!("/usr/local/bin/{bin_name}");
+
+    let mut layers = Vec::new();
+    let mut diff_ids = Vec::new();
+    if let Some(base) = opts
+        .base
+        .as_ref()
+        .and_then(|b| b.get(&target.arch.to_string()))
+    {
+        let blob = fs::read(base)?;
+        let mut rootfs = Vec::new();
+        GzDecoder::new(blob.as_slice()).read_to_end(&mut rootfs)?;
+        diff_ids.push(digest(&rootfs));
+        layers.push(layout.add(MEDIA_TYPE_LAYER, blob));
+    }
+    let layer = archive::tar(&[Entry {
+        path: bin_path.trim_start_matches('/').to_string(),
+        mode: 0o755,
+        data: &bin,
+    }])?;
+    diff_ids.push(digest(&layer));
+    layers.push(layout.add(MEDIA_TYPE_LAYER, archive::gzip(&layer)?));
+
+    let config = layout.add_json(
+        MEDIA_TYPE_CONFIG,
+        &json!({
+            "architecture": oci_arch(&target.arch),
+            "os": "linux",
+            "config": { "Entrypoint": [bin_path] },
+            "rootfs": { "type": "layers", "diff_ids": diff_ids },
+        }),
+    )?;
+    let mut manifest = layout.add_json(
+        MEDIA_TYPE_MANIFEST,
+        &json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_MANIFEST,
+            "config": config,
+            "layers": layers,
+        }),
+    )?;
+    manifest["platform"] = oci_platform(&target.arch);
+    Ok(manifest)
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &OciOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "oci");
+    let layout_dir = out_dir.join("layout");
+    fs::create_dir_all(&layout_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("oci").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let mut layout = Layout::default();
+    // images default to `scratch`, where only static (musl) builds run
+    let manifests = one_per_arch(
+        targets.iter().filter(|t| t.platform == Platform::Linux),
+        Libc::Musl,
+    )
+    .into_iter()
+    .map(|t| image(&mut layout, t, opts))
+    .collect::<Result<Vec<_>>>()?;
+    if manifests.is_empty() {
+        bail!("no targets available");
+    }
+
+    // a multi-arch index, tagged with the version in the layout's entry point
+    let mut annotations = json!({ "org.opencontainers.image.ref.name": version });
+    if let Some(image) = &opts.image {
+        annotations["io.containerd.image.name"] = json!(format!("{image}:{version}"));
+    }
+    let mut image_index = layout.add_json(
+        MEDIA_TYPE_INDEX,
+        &json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_INDEX,
+            "manifests": manifests,
+        }),
+    )?;
+    image_index["annotations"] = annotations;
+
+    let mut files = layout.blobs;
+    files.push((
+        "oci-layout".to_string(),
+        serde_json::to_vec(&json!({ "imageLayoutVersion": "1.0.0" }))?,
+    ));
+    files.push((
+        "index.json".to_string(),
+        serde_json::to_vec(&json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_INDEX,
+            "manifests": [image_index],
+        }))?,
+    ));
+    for (path, data) in &files {
+        let dest = layout_dir.join(path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(dest, data)?;
+    }
+    session.console.say(&format!(
+        "   {} {} ({} platform(s))",
+        style("layout").yellow(),
+        style(&layout_dir.to_string_lossy()).magenta(),
+        manifests.len()
+    ));
+
+    if opts.archive {
+        let entries = files
+            .iter()
+            .map(|(path, data)| Entry {
+                path: path.clone(),
+                mode: 0o644,
+                data,
+            })
+            .collect::<Vec<_>>();
+        let tarball = out_dir.join(format!("{}-{version}.oci.tar", opts.name));
+        fs::write(&tarball, archive::tar(&entries)?)?;
+        session.console.say(&format!(
+            "   {} {}",
+            style("archive").yellow(),
+            style(&tarball.to_string_lossy()).magenta()
+        ));
+    }
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+impl Provider for OciOpts {
+    fn name(&self) -> &'static str {
+        "oci"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "oci"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+
+    fn read_blob(layout: &Path, descriptor: &serde_json::Value) -> Vec<u8> {
+        let digest = descriptor["digest"].as_str().unwrap();
+        let data = fs::read(layout.join("blobs/sha256").join(&digest["sha256:".len()..])).unwrap();
+        assert_eq!(super::digest(&data), digest);
+        data
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/oci_test_generate"),
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::X64,
+                    archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::ARM64,
+                    archive: Some("fixtures/archives/recon-aarch64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Darwin,
+                    arch: Architecture::ARM64,
+                    ..Default::default()
+                },
+            ],
+            &OciOpts {
+                name: "recon".to_string(),
+                image: Some("ghcr.io/rusty-ferris-club/recon".to_string()),
+                archive: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let out = Path::new("out/oci_test_generate/recon-1.0.1/oci");
+        let layout = out.join("layout");
+        let index: serde_json::Value =
+            serde_json::from_slice(&fs::read(layout.join("index.json")).unwrap()).unwrap();
+        let entry = &index["manifests"][0];
+        assert_eq!(
+            entry["annotations"]["io.containerd.image.name"],
+            "ghcr.io/rusty-ferris-club/recon:1.0.1"
+        );
+
+        let image_index: serde_json::Value =
+            serde_json::from_slice(&read_blob(&layout, entry)).unwrap();
+        let platforms = image_index["manifests"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["platform"]["architecture"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(platforms, vec!["amd64", "arm64"]);
+
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&read_blob(&layout, &image_index["manifests"][0])).unwrap();
+        let config: serde_json::Value =
+            serde_json::from_slice(&read_blob(&layout, &manifest["config"])).unwrap();
+        assert_eq!(config["config"]["Entrypoint"][0], "/usr/local/bin/recon");
+
+        let layer = read_blob(&layout, &manifest["layers"][0]);
+        let mut tar = tar::Archive::new(GzDecoder::new(layer.as_slice()));
+        let paths = tar
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "usr/",
+                "usr/local/",
+                "usr/local/bin/",
+                "usr/local/bin/recon"
+            ]
+        );
+        assert!(out.join("recon-1.0.1.oci.tar").exists());
+    }
+}
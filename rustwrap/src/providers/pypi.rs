@@ -0,0 +1,519 @@
+#![allow(clippy::module_name_repetitions)]
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use base64::engine::fast_portable::{FastPortable, NO_PAD};
+use fs_err as fs;
+use regex::Regex;
+use serde::Deserialize;
+use sha2::Digest;
+
+use crate::{
+    archive::{self, Entry},
+    console::style,
+    data::{Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Action, Provider},
+};
+
+/// where `twine` uploads to without a `--repository-url`
+const DEFAULT_REPOSITORY_URL: &str = "https://upload.pypi.org/legacy/";
+const RECORD_ENGINE: FastPortable = FastPortable::from(&base64::alphabet::URL_SAFE, NO_PAD);
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PypiOpts {
+    pub name: String,
+    pub summary: String,
+    pub license: Option<String>,
+    pub homepage: Option<String>,
+    pub readme: Option<String>,
+    pub requires_python: Option<String>,
+    pub bin: Option<String>,
+    pub repository_url: Option<String>,
+    pub publish: bool,
+}
+
+impl PypiOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    /// distribution name as used in wheel file names
+    fn dist_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+/// platform tag for a target, `None` when pip has no tag for it
+fn platform_tag(target: &Target) -> Option<String> {
+    let linux = |arch: &str| match target.libc {
+        Some(Libc::Musl) => format!("musllinux_1_1_{arch}"),
+        Some(Libc::Gnu) | None => format!("manylinux_2_17_{arch}"),
+    };
+    Some(match (&target.platform, &target.arch) {
+        (Platform::Linux, Architecture::X64) => linux("x86_64"),
+        (Platform::Linux, Architecture::ARM64) => linux("aarch64"),
+        (Platform::Linux, Architecture::IA32) => linux("i686"),
+        (Platform::Linux, Architecture::ARM) => linux("armv7l"),
+        (Platform::Linux, Architecture::S390X) => linux("s390x"),
+        (Platform::Linux, Architecture::PPC64) => linux("ppc64le"),
+        // glibc only gained these architectures later than manylinux2014
+        (Platform::Linux, Architecture::RISCV64) => match target.libc {
+            Some(Libc::Musl) => linux("riscv64"),
+            _ => "manylinux_2_31_riscv64".to_string(),
+        },
+        (Platform::Linux, Architecture::LOONG64) => match target.libc {
+            Some(Libc::Musl) => linux("loongarch64"),
+            _ => "manylinux_2_36_loongarch64".to_string(),
+        },
+        (Platform::Darwin, Architecture::X64) => "macosx_10_12_x86_64".to_string(),
+        (Platform::Darwin, Architecture::ARM64) => "macosx_11_0_arm64".to_string(),
+        (Platform::Win32, Architecture::X64) => "win_amd64".to_string(),
+        (Platform::Win32, Architecture::ARM64) => "win_arm64".to_string(),
+        (Platform::Win32, Architecture::IA32) => "win32".to_string(),
+        _ => return None,
+    })
+}
+
+/// PEP 440 version from a semver (`1.0.0-beta.1` -> `1.0.0b1`)
+fn pep440_version(version: &str) -> Result<String> {
+    let v = semver::Version::parse(version)?;
+    let release = format!("{}.{}.{}", v.major, v.minor, v.patch);
+    if v.pre.is_empty() {
+        return Ok(release);
+    }
+    let (kind, num) = v.pre.split_once('.').unwrap_or((v.pre.as_str(), "0"));
+    let kind = match kind {
+        "alpha" | "a" => "a",
+        "beta" | "b" => "b",
+        "rc" => "rc",
+        "dev" => ".dev",
+        _ => bail!("cannot map pre-release '{}' to a PEP 440 version", v.pre),
+    };
+    Ok(format!("{release}{kind}{num}"))
+}
+
+/// semver from a PEP 440 version (`1.0.1rc2` -> `1.0.1-rc.2`, `1.0` -> `1.0.0`), undoing
+/// [`pep440_version`]. `None` for versions without a semver form, post and local parts are dropped
+fn semver_from_pep440(version: &str) -> Option<semver::Version> {
+    let re = Regex::new(
+        r"^v?(\d+)(?:\.(\d+))?(?:\.(\d+))?(?:[-_.]?(a|alpha|b|beta|c|rc|dev)[-_.]?(\d+)?)?(?:[-_.]?post[-_.]?\d*)?(?:\+.*)?$",
+    )
+    .unwrap();
+    let caps = re.captures(version.trim())?;
+    let num = |i| caps.get(i).map_or(Ok(0), |m| m.as_str().parse::<u64>());
+    let mut v = semver::Version::new(num(1).ok()?, num(2).ok()?, num(3).ok()?);
+    if let Some(kind) = caps.get(4) {
+        let kind = match kind.as_str() {
+            "a" | "alpha" => "alpha",
+            "b" | "beta" => "beta",
+            "c" | "rc" => "rc",
+            _ => "dev",
+        };
+        v.pre = semver::Prerelease::new(&format!("{kind}.{}", num(5).ok()?)).ok()?;
+    }
+    Some(v)
+}
+
+/// JSON API of the index `repository_url` uploads to, `None` for an index other than
+/// PyPI or TestPyPI, whose API is unknown
+fn json_api(opts: &PypiOpts) -> Option<&'static str> {
+    match opts
+        .repository_url
+        .as_deref()
+        .unwrap_or(DEFAULT_REPOSITORY_URL)
+        .trim_end_matches('/')
+    {
+        "https://upload.pypi.org/legacy" => Some("https://pypi.org/pypi"),
+        "https://test.pypi.org/legacy" => Some("https://test.pypi.org/pypi"),
+        _ => None,
+    }
+}
+
+/// Version last published to the index uploaded to, `None` if never published there, or
+/// if it is a custom index that cannot be asked
+pub fn latest(opts: &PypiOpts) -> Result<Option<semver::Version>> {
+    let Some(api) = json_api(opts) else {
+        tracing::info!(
+            repository_url = opts.repository_url,
+            "not checking the published version of a custom index"
+        );
+        return Ok(None);
+    };
+    let url = format!("{api}/{}/json", opts.name);
+    let resp = reqwest::blocking::Client::new()
+        .get(&url)
+        .header(reqwest::header::USER_AGENT, "rust-reqwest/rustwrap")
+        .send()?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        bail!(
+            "api request failed with status: {:?} - for: {:?}",
+            resp.status(),
+            url
+        )
+    }
+    let json = resp.json::<serde_json::Value>()?;
+    let v = json
+        .pointer("/info/version")
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| anyhow::format_err!("cannot find version at {url}"))?;
+    // a version rustwrap cannot compare is taken as unknown, rather than failing the run
+    let latest = semver_from_pep440(v);
+    if latest.is_none() {
+        tracing::warn!(
+            version = v,
+            "cannot map the published PEP 440 version to semver"
+        );
+    }
+    Ok(latest)
+}
+
+fn record_line(path: &str, data: &[u8]) -> String {
+    let hash = base64::encode_engine(sha2::Sha256::digest(data), &RECORD_ENGINE);
+    format!("{path},sha256={hash},{}\n", data.len())
+}
+
+fn metadata(version: &str, opts: &PypiOpts) -> Result<String> {
+    let mut out = String::new();
+    let _ = writeln!(out, "Metadata-Version: 2.1");
+    let _ = writeln!(out, "Name: {}", opts.name);
+    let _ = writeln!(out, "Version: {version}");
+    let _ = writeln!(out, "Summary: {}", opts.summary);
+    if let Some(homepage) = &opts.homepage {
+        let _ = writeln!(out, "Home-page: {homepage}");
+    }
+    if let Some(license) = &opts.license {
+        let _ = writeln!(out, "License: {license}");
+    }
+    if let Some(requires_python) = &opts.requires_python {
+        let _ = writeln!(out, "Requires-Python: {requires_python}");
+    }
+    if let Some(readme) = &opts.readme {
+        let _ = writeln!(out, "Description-Content-Type: text/markdown");
+        let _ = writeln!(out);
+        out.push_str(&fs::read_to_string(readme)?);
+    }
+    Ok(out)
+}
+
+fn wheel(version: &str, target: &Target, tag: &str, opts: &PypiOpts) -> Result<Vec<u8>> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let bin_name = target.bin_name(&opts.bin_name()).to_string();
+    let bin = archive::read_bin(Path::new(fname), &bin_name)?;
+
+    let prefix = format!("{}-{version}", opts.dist_name());
+    let metadata = metadata(version, opts)?;
+    let wheel = format!(
+        "Wheel-Version: 1.0\nGenerator: rustwrap ({})\nRoot-Is-Purelib: false\nTag: py3-none-{tag}\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    let mut entries = vec![
+        Entry {
+            path: format!("{prefix}.data/scripts/{bin_name}"),
+            mode: 0o755,
+            data: &bin,
+        },
+        Entry {
+            path: format!("{prefix}.dist-info/METADATA"),
+            mode: 0o644,
+            data: metadata.as_bytes(),
+        },
+        Entry {
+            path: format!("{prefix}.dist-info/WHEEL"),
+            mode: 0o644,
+            data: wheel.as_bytes(),
+        },
+    ];
+
+    let record_path = format!("{prefix}.dist-info/RECORD");
+    let mut record = entries
+        .iter()
+        .map(|e| record_line(&e.path, e.data))
+        .collect::<String>();
+    record.push_str(&format!("{record_path},,\n"));
+    entries.push(Entry {
+        path: record_path,
+        mode: 0o644,
+        data: record.as_bytes(),
+    });
+    archive::zip(&entries)
+}
+
+#[tracing::instrument(level = "trace", skip(session), err)]
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &PypiOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "pypi");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("pypi").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let py_version = pep440_version(version)?;
+    let mut wheels = 0;
+    for target in targets {
+        let Some(tag) = platform_tag(target) else {
+            continue;
+        };
+        let wheel_name = format!("{}-{py_version}-py3-none-{tag}.whl", opts.dist_name());
+        let wheel_path = out_dir.join(&wheel_name);
+        fs::write(&wheel_path, wheel(&py_version, target, &tag, opts)?)?;
+        session
+            .console
+            .say(&format!("   {} {}", style("wheel").yellow(), &wheel_name));
+        wheels += 1;
+    }
+    if wheels == 0 {
+        bail!("no targets available");
+    }
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+fn generated_wheels(out_dir: &Path, version: &str, opts: &PypiOpts) -> Result<Vec<PathBuf>> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "pypi");
+    let mut wheels = fs::read_dir(&out_dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    wheels.retain(|w| w.extension().is_some_and(|ext| ext == "whl"));
+    if wheels.is_empty() {
+        bail!("no wheels found in {out_dir:?}");
+    }
+    wheels.sort();
+    Ok(wheels)
+}
+
+pub fn plan(out_dir: &Path, version: &str, opts: &PypiOpts) -> Result<Vec<Action>> {
+    let destination = opts
+        .repository_url
+        .as_deref()
+        .unwrap_or(DEFAULT_REPOSITORY_URL);
+    Ok(generated_wheels(out_dir, version, opts)?
+        .iter()
+        .filter_map(|w| w.file_name())
+        .map(|w| Action::new(w.to_string_lossy(), destination))
+        .collect())
+}
+
+/// Upload the generated wheels with `twine`
+#[tracing::instrument(level = "trace", skip(session), err)]
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &PypiOpts,
+) -> Result<()> {
+    let wheels = generated_wheels(out_dir, version, opts)?;
+
+    let mut args = vec!["upload".into(), "--non-interactive".into()];
+    if let Some(repository_url) = &opts.repository_url {
+        args.push("--repository-url".into());
+        args.push(repository_url.into());
+    }
+    args.extend(wheels.into_iter().map(PathBuf::into_os_string));
+    let out = duct::cmd("twine", args).read()?;
+    session.console.say(&format!(
+        "{} {} published:\n{out}",
+        crate::console::PKG,
+        style("pypi").green()
+    ));
+    Ok(())
+}
+
+impl Provider for PypiOpts {
+    fn name(&self) -> &'static str {
+        "pypi"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "pypi"))
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn latest_version(&self) -> Result<Option<semver::Version>> {
+        latest(self)
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        generated_wheels(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/pypi_test_generate"),
+            "1.0.1-rc.2",
+            &[
+                Target {
+                    platform: Platform::Linux,
+                    arch: Architecture::X64,
+                    archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::X64,
+                    archive: Some("fixtures/archives/recon-x86_64-windows.zip".to_string()),
+                    ..Default::default()
+                },
+            ],
+            &PypiOpts {
+                name: "recon-tool".to_string(),
+                summary: "recon tool".to_string(),
+                bin: Some("recon".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let out = Path::new("out/pypi_test_generate/recon-tool-1.0.1-rc.2/pypi");
+        assert!(out
+            .join("recon_tool-1.0.1rc2-py3-none-win_amd64.whl")
+            .exists());
+        let mut whl = zip::ZipArchive::new(
+            fs::File::open(out.join("recon_tool-1.0.1rc2-py3-none-manylinux_2_17_x86_64.whl"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            whl.by_name("recon_tool-1.0.1rc2.data/scripts/recon")
+                .unwrap()
+                .unix_mode(),
+            Some(0o100_755)
+        );
+        let mut wheel = String::new();
+        whl.by_name("recon_tool-1.0.1rc2.dist-info/WHEEL")
+            .unwrap()
+            .read_to_string(&mut wheel)
+            .unwrap();
+        assert!(wheel.contains("Tag: py3-none-manylinux_2_17_x86_64\n"));
+
+        let mut record = String::new();
+        whl.by_name("recon_tool-1.0.1rc2.dist-info/RECORD")
+            .unwrap()
+            .read_to_string(&mut record)
+            .unwrap();
+        let lines = record.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("recon_tool-1.0.1rc2.data/scripts/recon,sha256="));
+        assert_eq!(lines[3], "recon_tool-1.0.1rc2.dist-info/RECORD,,");
+    }
+
+    #[test]
+    fn test_pep440_version() {
+        assert_eq!(pep440_version("1.0.1").unwrap(), "1.0.1");
+        assert_eq!(pep440_version("1.0.1-beta.3").unwrap(), "1.0.1b3");
+        assert!(pep440_version("1.0.1-nightly").is_err());
+    }
+
+    #[test]
+    fn test_semver_from_pep440() {
+        // an rc published by rustwrap comes back from the registry in its PEP 440 form
+        let published = pep440_version("1.0.1-rc.2").unwrap();
+        assert_eq!(published, "1.0.1rc2");
+        assert_eq!(
+            semver_from_pep440(&published),
+            Some(semver::Version::parse("1.0.1-rc.2").unwrap())
+        );
+        assert_eq!(
+            semver_from_pep440("1.0"),
+            Some(semver::Version::parse("1.0.0").unwrap())
+        );
+        assert_eq!(
+            semver_from_pep440("2.1.0.dev3"),
+            Some(semver::Version::parse("2.1.0-dev.3").unwrap())
+        );
+        assert_eq!(semver_from_pep440("2019.10.1.4"), None);
+    }
+
+    #[test]
+    fn test_json_api() {
+        let api = |repository_url: Option<&str>| {
+            json_api(&PypiOpts {
+                repository_url: repository_url.map(ToString::to_string),
+                ..Default::default()
+            })
+        };
+        assert_eq!(api(None), Some("https://pypi.org/pypi"));
+        assert_eq!(
+            api(Some("https://test.pypi.org/legacy/")),
+            Some("https://test.pypi.org/pypi")
+        );
+        assert_eq!(api(Some("https://pypi.example.com/simple/")), None);
+    }
+
+    #[test]
+    fn test_platform_tag() {
+        let tag = |platform, arch, libc| {
+            platform_tag(&Target {
+                platform,
+                arch,
+                libc,
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            tag(Platform::Linux, Architecture::ARM, None).as_deref(),
+            Some("manylinux_2_17_armv7l")
+        );
+        assert_eq!(
+            tag(Platform::Linux, Architecture::X64, Some(Libc::Musl)).as_deref(),
+            Some("musllinux_1_1_x86_64")
+        );
+        assert_eq!(
+            tag(Platform::Linux, Architecture::RISCV64, Some(Libc::Gnu)).as_deref(),
+            Some("manylinux_2_31_riscv64")
+        );
+        assert_eq!(tag(Platform::FreeBSD, Architecture::X64, None), None);
+    }
+}
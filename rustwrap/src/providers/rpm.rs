@@ -0,0 +1,227 @@
+#![allow(clippy::module_name_repetitions)]
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use rpm::{BuildConfig, CompressionType, Dependency, FileOptions, PackageBuilder};
+use serde::Deserialize;
+
+use crate::{
+    archive,
+    console::style,
+    data::{one_per_arch, Architecture, Libc, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+#[derive(Deserialize, Default)]
+pub struct RpmOpts {
+    pub name: String,
+    pub license: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub release: Option<String>,
+    pub requires: Option<Vec<String>>,
+    pub bin: Option<String>,
+}
+
+impl RpmOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    fn release(&self) -> String {
+        self.release.clone().unwrap_or_else(|| "1".to_string())
+    }
+}
+
+fn rpm_arch(arch: &Architecture) -> &'static str {
+    match arch {
+        Architecture::X64 => "x86_64",
+        Architecture::ARM64 => "aarch64",
+        Architecture::IA32 => "i686",
+        Architecture::ARM => "armv7hl",
+        Architecture::RISCV64 => "riscv64",
+        Architecture::S390X => "s390x",
+        Architecture::PPC64 => "ppc64le",
+        Architecture::LOONG64 => "loongarch64",
+    }
+}
+
+/// semver pre-releases sort before the release, which is `~` in rpm versions
+fn rpm_version(version: &str) -> String {
+    version.replace('-', "~")
+}
+
+/// parse `name`, or `name <op> version` (e.g. `glibc >= 2.17`)
+fn dependency(requirement: &str) -> Result<Dependency> {
+    let parts = requirement.split_whitespace().collect::<Vec<_>>();
+    Ok(match parts.as_slice() {
+        [name] => Dependency::any(*name),
+        [name, "=", v] | [name, "==", v] => Dependency::eq(*name, *v),
+        [name, ">=", v] => Dependency::greater_eq(*name, *v),
+        [name, ">", v] => Dependency::greater(*name, *v),
+        [name, "<=", v] => Dependency::less_eq(*name, *v),
+        [name, "<", v] => Dependency::less(*name, *v),
+        _ => bail!("cannot parse requirement: '{requirement}'"),
+    })
+}
+
+fn build(version: &str, target: &Target, opts: &RpmOpts) -> Result<rpm::Package> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let bin_name = target.bin_name(&opts.bin_name()).to_string();
+    let bin = archive::read_bin(Path::new(fname), &bin_name)?;
+
+    let mut builder = PackageBuilder::new(
+        &opts.name,
+        &rpm_version(version),
+        &opts.license,
+        rpm_arch(&target.arch),
+        &opts.summary,
+    );
+    builder
+        .using_config(
+            BuildConfig::v4()
+                .compression(CompressionType::Gzip)
+                .source_date(0u32),
+        )
+        .release(opts.release())
+        .description(opts.description.as_ref().unwrap_or(&opts.summary))
+        .with_file_contents(
+            bin,
+            FileOptions::new(format!("/usr/bin/{bin_name}")).permissions(0o755),
+        )?;
+    if let Some(url) = &opts.url {
+        builder.url(url);
+    }
+    for requirement in opts.requires.iter().flatten() {
+        builder.requires(dependency(requirement)?);
+    }
+    Ok(builder.build()?)
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &RpmOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "rpm");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("rpm").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let linux_targets = one_per_arch(
+        targets.iter().filter(|t| t.platform == Platform::Linux),
+        Libc::Gnu,
+    );
+    if linux_targets.is_empty() {
+        bail!("no targets available");
+    }
+
+    for target in linux_targets {
+        let pkg = build(version, target, opts)?;
+        let rpm_name = format!(
+            "{}-{}-{}.{}.rpm",
+            opts.name,
+            rpm_version(version),
+            opts.release(),
+            rpm_arch(&target.arch)
+        );
+        pkg.write_file(out_dir.join(&rpm_name))?;
+        session
+            .console
+            .say(&format!("   {} {}", style("package").yellow(), &rpm_name));
+    }
+    session.console.say(&format!("{prefix} done."));
+    Ok(())
+}
+
+impl Provider for RpmOpts {
+    fn name(&self) -> &'static str {
+        "rpm"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "rpm"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/rpm_test_generate"),
+            "1.0.1",
+            &[Target {
+                platform: Platform::Linux,
+                arch: Architecture::X64,
+                archive: Some("fixtures/archives/recon-x86_64-linux.tar.gz".to_string()),
+                ..Default::default()
+            }],
+            &RpmOpts {
+                name: "recon".to_string(),
+                license: "Apache-2.0".to_string(),
+                summary: "recon tool".to_string(),
+                url: Some("https://github.com/rusty-ferris-club/recon".to_string()),
+                requires: Some(vec!["glibc >= 2.17".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let pkg =
+            rpm::Package::open("out/rpm_test_generate/recon-1.0.1/rpm/recon-1.0.1-1.x86_64.rpm")
+                .unwrap();
+        assert_eq!(pkg.metadata.get_name().unwrap(), "recon");
+        assert_eq!(pkg.metadata.get_arch().unwrap(), "x86_64");
+        assert_eq!(pkg.metadata.get_license().unwrap(), "Apache-2.0");
+        assert_eq!(
+            pkg.metadata.get_file_paths().unwrap(),
+            vec![PathBuf::from("/usr/bin/recon")]
+        );
+        assert!(pkg
+            .metadata
+            .get_requires()
+            .unwrap()
+            .iter()
+            .any(|r| r.name == "glibc" && r.version == "2.17"));
+    }
+
+    #[test]
+    fn test_dependency() {
+        assert!(dependency("glibc").is_ok());
+        assert!(dependency("glibc >= 2.17").is_ok());
+        assert!(dependency("glibc >=").is_err());
+    }
+}
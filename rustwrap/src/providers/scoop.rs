@@ -0,0 +1,319 @@
+#![allow(clippy::module_name_repetitions)]
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use fs_err as fs;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    archive,
+    console::style,
+    data::{Architecture, Platform, Session, Target},
+    providers::{
+        diff,
+        github::{read_file, update_file},
+        package_dir, Action, Provider,
+    },
+};
+
+const VAR_VERSION: &str = "__VERSION__";
+
+#[derive(Deserialize, Default)]
+pub struct ScoopOpts {
+    pub name: String,
+    pub bucket: String,
+    pub manifest_fname: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub bin: Option<String>,
+    pub publish: bool,
+}
+
+impl ScoopOpts {
+    /// path of the manifest inside the bucket repo
+    fn manifest_file(&self) -> String {
+        self.manifest_fname
+            .clone()
+            .unwrap_or_else(|| format!("bucket/{}.json", self.name))
+    }
+
+    /// the manifest is saved locally without the bucket folders
+    fn local_manifest_file(&self) -> Result<String> {
+        let fname = self.manifest_file();
+        Ok(Path::new(&fname)
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("malformed manifest file name: {fname}"))?
+            .to_string_lossy()
+            .to_string())
+    }
+
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+}
+
+/// scoop architecture for a target, `None` when scoop has none for it
+fn scoop_arch(arch: &Architecture) -> Option<&'static str> {
+    match arch {
+        Architecture::X64 => Some("64bit"),
+        Architecture::ARM64 => Some("arm64"),
+        Architecture::IA32 => Some("32bit"),
+        _ => None,
+    }
+}
+
+/// Version of the manifest currently in the bucket, `None` if there is none yet
+pub fn latest(opts: &ScoopOpts) -> Result<Option<semver::Version>> {
+    let manifest_file = opts.manifest_file();
+    let Some(content) = read_file(&opts.bucket, &manifest_file)? else {
+        return Ok(None);
+    };
+    let manifest: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("cannot parse manifest {manifest_file}"))?;
+    let v = manifest
+        .pointer("/version")
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| anyhow::format_err!("cannot find version in {manifest_file}"))?;
+    Ok(Some(semver::Version::parse(v)?))
+}
+
+fn manifest(
+    session: &Session<'_>,
+    version: &str,
+    targets: &[&Target],
+    opts: &ScoopOpts,
+) -> Result<serde_json::Value> {
+    let mut architecture = serde_json::Map::new();
+    let mut autoupdate = serde_json::Map::new();
+    for target in targets {
+        let Some(arch) = scoop_arch(&target.arch) else {
+            continue;
+        };
+        let fname = target
+            .archive
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+        let sha = archive::sha256(Path::new(fname))?;
+
+        let mut details = json!({ "url": target.url(version), "hash": sha });
+        let mut update = json!({ "url": target.url_template.replace(VAR_VERSION, "$version") });
+        if let Some(dir) = archive::root_dir(Path::new(fname))? {
+            update["extract_dir"] = json!(dir.replace(version, "$version"));
+            details["extract_dir"] = json!(dir);
+        }
+        architecture.insert(arch.to_string(), details);
+        autoupdate.insert(arch.to_string(), update);
+    }
+
+    let bin_name = opts.bin_name();
+    let bin = targets.first().map_or_else(
+        || format!("{bin_name}.exe").into(),
+        |t| t.bin_name(&bin_name),
+    );
+    let mut manifest = json!({
+        "version": version,
+        "description": opts.description,
+        "homepage": opts.homepage,
+        "license": opts.license,
+        "architecture": architecture,
+        "bin": bin,
+    });
+    if let Some(repo) = session.config.repo.as_ref() {
+        manifest["checkver"] = json!({ "github": format!("https://github.com/{repo}") });
+        manifest["autoupdate"] = json!({ "architecture": autoupdate });
+    }
+
+    // scoop rejects explicit nulls, drop the fields that were not configured
+    let fields = manifest.as_object_mut().expect("malformed json");
+    fields.retain(|_, v| !v.is_null());
+    Ok(manifest)
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &ScoopOpts,
+) -> Result<()> {
+    let out_dir = package_dir(out_dir, &opts.name, version, "scoop");
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("scoop").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let win_targets = targets
+        .iter()
+        .filter(|t| t.platform == Platform::Win32 && scoop_arch(&t.arch).is_some())
+        .collect::<Vec<_>>();
+    if win_targets.is_empty() {
+        bail!("no targets available");
+    }
+
+    let manifest = manifest(session, version, &win_targets, opts)?;
+    let content = serde_json::to_string_pretty(&manifest)?;
+    tracing::info!(content, "rendered manifest");
+
+    let dest_file = out_dir.join(opts.local_manifest_file()?);
+    fs::write(&dest_file, content)?;
+    session.console.say(&format!(
+        "{} saved manifest to '{}'",
+        prefix,
+        style(&dest_file.to_string_lossy()).magenta(),
+    ));
+
+    Ok(())
+}
+
+fn generated_manifest(out_dir: &Path, version: &str, opts: &ScoopOpts) -> Result<String> {
+    Ok(fs::read_to_string(
+        package_dir(out_dir, &opts.name, version, "scoop").join(opts.local_manifest_file()?),
+    )?)
+}
+
+/// The manifest update, with a diff against the manifest currently in the bucket
+pub fn plan(out_dir: &Path, version: &str, opts: &ScoopOpts) -> Result<Vec<Action>> {
+    let fname = opts.manifest_file();
+    let content = generated_manifest(out_dir, version, opts)?;
+    let current = read_file(&opts.bucket, &fname)?;
+    Ok(vec![Action {
+        diff: diff(&fname, current.as_deref(), &content),
+        ..Action::new(&fname, format!("github.com/{}", opts.bucket))
+    }])
+}
+
+pub fn publish(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    opts: &ScoopOpts,
+) -> Result<()> {
+    let fname = opts.manifest_file();
+    let content = generated_manifest(out_dir, version, opts)?;
+    update_file(&opts.bucket, &fname, &content)?;
+    session.console.say(&format!(
+        "{} {} published '{}' in '{}'",
+        crate::console::PKG,
+        style("scoop").green(),
+        style(&fname).magenta(),
+        style(&opts.bucket).magenta()
+    ));
+    Ok(())
+}
+
+impl Provider for ScoopOpts {
+    fn name(&self) -> &'static str {
+        "scoop"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "scoop"))
+    }
+
+    fn publishes(&self) -> bool {
+        self.publish
+    }
+
+    fn latest_version(&self) -> Result<Option<semver::Version>> {
+        latest(self)
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+
+    fn plan(&self, out_dir: &Path, version: &str) -> Result<Vec<Action>> {
+        plan(out_dir, version, self)
+    }
+
+    fn verify(&self, out_dir: &Path, version: &str) -> Result<()> {
+        generated_manifest(out_dir, version, self).map(drop)
+    }
+
+    fn publish(&self, session: &mut Session<'_>, out_dir: &Path, version: &str) -> Result<()> {
+        publish(session, out_dir, version, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_generate() {
+        let config = Config {
+            repo: Some("rusty-ferris-club/recon".to_string()),
+            ..Default::default()
+        };
+        let mut session = Session {
+            config: &config,
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/scoop_test_generate"),
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::X64,
+                    url_template: "https://example.com/v__VERSION__/recon-x86_64-windows.zip"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-x86_64-windows.zip".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Darwin,
+                    arch: Architecture::ARM64,
+                    ..Default::default()
+                },
+            ],
+            &ScoopOpts {
+                name: "recon".to_string(),
+                bucket: "rusty-ferris-club/scoop-bucket".to_string(),
+                description: Some("recon tool".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let manifest: serde_json::Value = serde_json::from_reader(
+            fs::File::open("out/scoop_test_generate/recon-1.0.1/scoop/recon.json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manifest["version"], "1.0.1");
+        assert_eq!(manifest["bin"], "recon.exe");
+        assert_eq!(manifest.get("license"), None);
+        assert_eq!(
+            manifest["architecture"]["64bit"]["url"],
+            "https://example.com/v1.0.1/recon-x86_64-windows.zip"
+        );
+        assert_eq!(
+            manifest["architecture"]["64bit"]["hash"],
+            archive::sha256(Path::new("fixtures/archives/recon-x86_64-windows.zip")).unwrap()
+        );
+        assert_eq!(
+            manifest["architecture"]["64bit"]["extract_dir"],
+            "recon-x86_64-windows"
+        );
+        assert_eq!(
+            manifest["autoupdate"]["architecture"]["64bit"]["url"],
+            "https://example.com/v$version/recon-x86_64-windows.zip"
+        );
+        assert_eq!(manifest.pointer("/architecture/arm64"), None);
+    }
+}
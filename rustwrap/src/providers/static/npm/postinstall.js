@@ -3,7 +3,12 @@ const { platform, arch } = process;
 const INFO = require('./info.json');
 const name = INFO.name;
 
-const binName = INFO.platforms.find((p) => p.platform == platform && p.arch == arch)?.bin;
+const candidates = INFO.platforms.filter((p) => p.platform == platform && p.arch == arch);
+// prefer a build for this libc, then any build that runs here
+const libc = platform == "linux"
+	? (process.report?.getReport().header.glibcVersionRuntime ? "glibc" : "musl")
+	: null;
+const binName = (candidates.find((p) => (p.libc ?? null) == libc) ?? candidates[0])?.bin;
 if (binName) {
 	let binPath;
 	try {
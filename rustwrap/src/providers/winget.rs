@@ -0,0 +1,337 @@
+#![allow(clippy::module_name_repetitions)]
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archive,
+    console::style,
+    data::{Architecture, Platform, Session, Target},
+    providers::{package_dir, Provider},
+};
+
+const MANIFEST_VERSION: &str = "1.4.0";
+const DEFAULT_LOCALE: &str = "en-US";
+
+#[derive(Deserialize, Default)]
+pub struct WingetOpts {
+    pub name: String,
+    pub package_identifier: String,
+    pub publisher: String,
+    pub package_name: Option<String>,
+    pub license: String,
+    pub short_description: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub publisher_url: Option<String>,
+    pub license_url: Option<String>,
+    pub moniker: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub bin: Option<String>,
+}
+
+impl WingetOpts {
+    fn bin_name(&self) -> String {
+        self.bin.as_ref().unwrap_or(&self.name).to_string()
+    }
+
+    /// folder layout used by winget-pkgs: `manifests/p/Publisher/Package/<version>`
+    fn manifest_dir(&self, version: &str) -> PathBuf {
+        let first = self
+            .package_identifier
+            .chars()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+            .to_string();
+        let mut dir = PathBuf::from("manifests").join(first);
+        for part in self.package_identifier.split('.') {
+            dir.push(part);
+        }
+        dir.join(version)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct VersionManifest<'a> {
+    package_identifier: &'a str,
+    package_version: &'a str,
+    default_locale: &'a str,
+    manifest_type: &'a str,
+    manifest_version: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct LocaleManifest<'a> {
+    package_identifier: &'a str,
+    package_version: &'a str,
+    package_locale: &'a str,
+    publisher: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publisher_url: Option<&'a str>,
+    package_name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package_url: Option<&'a str>,
+    license: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_url: Option<&'a str>,
+    short_description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moniker: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a [String]>,
+    manifest_type: &'a str,
+    manifest_version: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct NestedInstallerFile {
+    relative_file_path: String,
+    portable_command_alias: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Installer {
+    architecture: &'static str,
+    installer_url: String,
+    installer_sha256: String,
+    nested_installer_files: Vec<NestedInstallerFile>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct InstallerManifest<'a> {
+    package_identifier: &'a str,
+    package_version: &'a str,
+    installer_type: &'a str,
+    nested_installer_type: &'a str,
+    installers: Vec<Installer>,
+    manifest_type: &'a str,
+    manifest_version: &'a str,
+}
+
+/// winget architecture for a target, `None` when winget has none for it
+fn winget_arch(arch: &Architecture) -> Option<&'static str> {
+    match arch {
+        Architecture::X64 => Some("x64"),
+        Architecture::ARM64 => Some("arm64"),
+        Architecture::IA32 => Some("x86"),
+        Architecture::ARM => Some("arm"),
+        _ => None,
+    }
+}
+
+fn installer(
+    version: &str,
+    target: &Target,
+    architecture: &'static str,
+    opts: &WingetOpts,
+) -> Result<Installer> {
+    let fname = target
+        .archive
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+    let bin_name = target.bin_name(&opts.bin_name()).to_string();
+    let relative_file_path = match archive::root_dir(Path::new(fname))? {
+        Some(dir) => format!("{dir}\\{bin_name}"),
+        None => bin_name,
+    };
+    Ok(Installer {
+        architecture,
+        installer_url: target.url(version),
+        installer_sha256: archive::sha256(Path::new(fname))?.to_uppercase(),
+        nested_installer_files: vec![NestedInstallerFile {
+            relative_file_path,
+            portable_command_alias: opts.bin_name(),
+        }],
+    })
+}
+
+fn write_manifest<T: Serialize>(path: &Path, manifest_type: &str, manifest: &T) -> Result<()> {
+    let content = format!(
+        "# yaml-language-server: $schema=https://aka.ms/winget-manifest.{manifest_type}.{MANIFEST_VERSION}.schema.json\n\n{}",
+        serde_yaml::to_string(manifest)?
+    );
+    fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn generate(
+    session: &mut Session<'_>,
+    out_dir: &Path,
+    version: &str,
+    targets: &[Target],
+    opts: &WingetOpts,
+) -> Result<()> {
+    let out_dir =
+        package_dir(out_dir, &opts.name, version, "winget").join(opts.manifest_dir(version));
+    fs::create_dir_all(&out_dir)?;
+
+    let prefix = format!("{} {}", crate::console::PKG, style("winget").green());
+    session.console.say(&format!(
+        "{} generating into {}",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta()
+    ));
+
+    let installers = targets
+        .iter()
+        .filter(|t| t.platform == Platform::Win32)
+        .filter_map(|t| winget_arch(&t.arch).map(|arch| installer(version, t, arch, opts)))
+        .collect::<Result<Vec<_>>>()?;
+    if installers.is_empty() {
+        bail!("no targets available");
+    }
+
+    let id = opts.package_identifier.as_str();
+    write_manifest(
+        &out_dir.join(format!("{id}.yaml")),
+        "version",
+        &VersionManifest {
+            package_identifier: id,
+            package_version: version,
+            default_locale: DEFAULT_LOCALE,
+            manifest_type: "version",
+            manifest_version: MANIFEST_VERSION,
+        },
+    )?;
+    write_manifest(
+        &out_dir.join(format!("{id}.locale.{DEFAULT_LOCALE}.yaml")),
+        "defaultLocale",
+        &LocaleManifest {
+            package_identifier: id,
+            package_version: version,
+            package_locale: DEFAULT_LOCALE,
+            publisher: &opts.publisher,
+            publisher_url: opts.publisher_url.as_deref(),
+            package_name: opts.package_name.as_ref().unwrap_or(&opts.name),
+            package_url: opts.homepage.as_deref(),
+            license: &opts.license,
+            license_url: opts.license_url.as_deref(),
+            short_description: &opts.short_description,
+            description: opts.description.as_deref(),
+            moniker: opts.moniker.as_deref(),
+            tags: opts.tags.as_deref(),
+            manifest_type: "defaultLocale",
+            manifest_version: MANIFEST_VERSION,
+        },
+    )?;
+    write_manifest(
+        &out_dir.join(format!("{id}.installer.yaml")),
+        "installer",
+        &InstallerManifest {
+            package_identifier: id,
+            package_version: version,
+            installer_type: "zip",
+            nested_installer_type: "portable",
+            installers,
+            manifest_type: "installer",
+            manifest_version: MANIFEST_VERSION,
+        },
+    )?;
+
+    session.console.say(&format!(
+        "{} saved manifests to '{}'",
+        prefix,
+        style(&out_dir.to_string_lossy()).magenta(),
+    ));
+    Ok(())
+}
+
+impl Provider for WingetOpts {
+    fn name(&self) -> &'static str {
+        "winget"
+    }
+
+    fn output_dir(&self, out_dir: &Path, version: &str) -> Option<PathBuf> {
+        Some(package_dir(out_dir, &self.name, version, "winget"))
+    }
+
+    fn generate(
+        &self,
+        session: &mut Session<'_>,
+        out_dir: &Path,
+        version: &str,
+        targets: &[Target],
+    ) -> Result<()> {
+        generate(session, out_dir, version, targets, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::MemConsole, data::Config};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_generate() {
+        let mut session = Session {
+            config: &Config::default(),
+            console: &mut MemConsole::default(),
+        };
+        generate(
+            &mut session,
+            Path::new("out/winget_test_generate"),
+            "1.0.1",
+            &[
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::X64,
+                    url_template: "https://example.com/v__VERSION__/recon-x86_64-windows.zip"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-x86_64-windows.zip".to_string()),
+                    ..Default::default()
+                },
+                Target {
+                    platform: Platform::Win32,
+                    arch: Architecture::ARM64,
+                    url_template: "https://example.com/v__VERSION__/recon-aarch64-windows.zip"
+                        .to_string(),
+                    archive: Some("fixtures/archives/recon-aarch64-windows.zip".to_string()),
+                    ..Default::default()
+                },
+            ],
+            &WingetOpts {
+                name: "recon".to_string(),
+                package_identifier: "RustyFerrisClub.Recon".to_string(),
+                publisher: "Rusty Ferris Club".to_string(),
+                license: "Apache-2.0".to_string(),
+                short_description: "recon tool".to_string(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let dir = Path::new(
+            "out/winget_test_generate/recon-1.0.1/winget/manifests/r/RustyFerrisClub/Recon/1.0.1",
+        );
+        let installer: serde_yaml::Value = serde_yaml::from_reader(
+            fs::File::open(dir.join("RustyFerrisClub.Recon.installer.yaml")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(installer["InstallerType"], "zip");
+        assert_eq!(installer["Installers"][1]["Architecture"], "arm64");
+        assert_eq!(
+            installer["Installers"][0]["InstallerSha256"],
+            archive::sha256(Path::new("fixtures/archives/recon-x86_64-windows.zip"))
+                .unwrap()
+                .to_uppercase()
+                .as_str()
+        );
+        assert_eq!(
+            installer["Installers"][0]["NestedInstallerFiles"][0]["RelativeFilePath"],
+            "recon-x86_64-windows\\recon.exe"
+        );
+        assert!(dir.join("RustyFerrisClub.Recon.yaml").exists());
+        assert!(dir.join("RustyFerrisClub.Recon.locale.en-US.yaml").exists());
+    }
+}
@@ -1,19 +1,88 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
+use crate::archive;
+use crate::cache::Cache;
 use crate::console::EnvConsole;
-use crate::data::{Config, Session};
+use crate::data::{Config, Release, Session};
 use crate::download::TargetsDownloader;
-use crate::providers::npm;
-use crate::providers::{brew, github};
+use crate::infer;
+use crate::providers::{github, Action, Provider, Registry};
 use anyhow::{bail, Result};
 use console::style;
+use fs_err as fs;
 
-/// Run a wrap workflow
+const RELEASES_DIR: &str = "releases";
+const CHECKSUMS_TXT: &str = "checksums.txt";
+const CHECKSUMS_JSON: &str = "checksums.json";
+
+/// Run a wrap workflow with the built-in providers: download, generate and publish.
+/// With `dry_run`, everything is generated but nothing is published, and a plan of
+/// what would have been published is printed instead. With `cache`, downloads go through
+/// the local download cache.
+///
+/// # Errors
+///
+/// This function will return an error if an IO failed
+pub fn run(
+    version: Option<String>,
+    config_file: &Path,
+    out_path: &Path,
+    dry_run: bool,
+    cache: bool,
+) -> Result<()> {
+    run_with(
+        &Registry::default(),
+        version,
+        config_file,
+        out_path,
+        dry_run,
+        cache,
+    )
+}
+
+/// Run a wrap workflow, with providers from `registry`
 ///
 /// # Errors
 ///
 /// This function will return an error if an IO failed
-pub fn run(version: Option<String>, config_file: &Path, out_path: &Path) -> Result<()> {
+pub fn run_with(
+    registry: &Registry,
+    version: Option<String>,
+    config_file: &Path,
+    out_path: &Path,
+    dry_run: bool,
+    cache: bool,
+) -> Result<()> {
+    // fail on a bad provider config before spending time on downloads
+    load(registry, config_file)?;
+    download(version, config_file, out_path, cache)?;
+    generate(registry, config_file, out_path)?;
+    publish(registry, config_file, out_path, dry_run)
+}
+
+fn load(registry: &Registry, config_file: &Path) -> Result<(Config, Vec<Box<dyn Provider>>)> {
+    let config = Config::load(config_file)?;
+    let providers = registry.load(&config)?;
+    for provider in &providers {
+        provider.validate()?;
+    }
+    Ok((config, providers))
+}
+
+/// Download stage: fetch target archives into `<out_path>/releases`, and record them
+/// for the `generate`, `publish` and `verify` stages. With `cache`, archives are reused
+/// from, and kept in, the local download cache.
+///
+/// # Errors
+///
+/// This function will return an error if a download failed
+pub fn download(
+    version: Option<String>,
+    config_file: &Path,
+    out_path: &Path,
+    cache: bool,
+) -> Result<()> {
     let config = Config::load(config_file)?;
     let mut session = Session {
         config: &config,
@@ -40,49 +109,307 @@ pub fn run(version: Option<String>, config_file: &Path, out_path: &Path) -> Resu
         v
     };
 
-    let releases_path = out_path.join("releases");
-    let downloader = TargetsDownloader::new(&config.targets, &releases_path);
-    let versioned_targets = downloader.download(&mut session, &target_v.to_string())?;
+    let releases_path = out_path.join(RELEASES_DIR);
+    let version = target_v.to_string();
+    let mut targets = config.targets.clone();
+    if config.auto_targets {
+        let Some(repo) = config.repo.as_ref() else {
+            bail!("set repo in your configuration to use auto_targets");
+        };
+        let assets = github::release_assets(repo, &version)?;
+        // targets in the configuration win over discovered ones
+        let discovered = infer::targets(&assets, &version)
+            .into_iter()
+            .filter(|d| {
+                !targets
+                    .iter()
+                    .any(|t| t.platform == d.platform && t.arch == d.arch && t.libc == d.libc)
+            })
+            .collect::<Vec<_>>();
+        session.console.say(&format!(
+            "{} discovered {} target(s) in {} assets of {repo} {version}",
+            crate::console::INFO,
+            discovered.len(),
+            assets.len()
+        ));
+        targets.extend(discovered);
+    }
+    if targets.is_empty() {
+        bail!("no targets to download, add `targets` or turn on `auto_targets`");
+    }
+    let downloader = TargetsDownloader::new(&targets, &releases_path)
+        .cache(cache.then(Cache::default_dir).flatten().map(Cache::new));
+    let targets = downloader.download(&mut session, &version)?;
+    let checksums = targets
+        .iter()
+        .filter_map(|t| t.archive.as_ref())
+        .map(|a| Ok((a.clone(), archive::sha256(Path::new(a))?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    Release {
+        version,
+        targets,
+        checksums,
+    }
+    .save(&releases_path)
+}
+
+/// Generate stage: render packages for every configured provider from the archives
+/// the download stage left in `out_path`
+///
+/// # Errors
+///
+/// This function will return an error if nothing was downloaded, or rendering failed
+pub fn generate(registry: &Registry, config_file: &Path, out_path: &Path) -> Result<()> {
+    let (config, providers) = load(registry, config_file)?;
+    let release = Release::load(&out_path.join(RELEASES_DIR))?;
+    let mut session = Session {
+        config: &config,
+        console: &mut EnvConsole {},
+    };
+    for provider in &providers {
+        provider.generate(&mut session, out_path, &release.version, &release.targets)?;
+    }
+    let output_dirs = providers
+        .iter()
+        .filter_map(|p| p.output_dir(out_path, &release.version))
+        .collect::<Vec<_>>();
+    let count = write_checksums(out_path, &release, &output_dirs)?;
+    session.console.say(&format!(
+        "{} wrote checksums of {count} file(s) to {}",
+        crate::console::INFO,
+        style(out_path.join(CHECKSUMS_TXT).display()).magenta()
+    ));
+    Ok(())
+}
+
+/// `/` separated path of `path` relative to `out_path`
+fn relative(out_path: &Path, path: &Path) -> String {
+    path.strip_prefix(out_path)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-    if let Some(npm) = config.npm.as_ref() {
-        let prefix = format!("{} {}", crate::console::PKG, style("brew").green());
-        let latest_v = npm::latest(npm)?;
-        if latest_v < target_v {
-            session.console.say(&format!(
-                "{prefix} current: {latest_v}, publishing: {target_v}..."
-            ));
-            npm::publish(
-                &mut session,
-                out_path,
-                &target_v.to_string(),
-                &versioned_targets,
-                npm,
-            )?;
-        } else {
-            session
-                .console
-                .say(&format!("{prefix} discovered version ({latest_v}) higher/equal to target version ({target_v}), skipping."));
+/// Write `checksums.txt` (as `sha256sum` does) and `checksums.json` covering the downloaded
+/// archives of `release` and the files in `output_dirs`, returns how many files are covered
+fn write_checksums(out_path: &Path, release: &Release, output_dirs: &[PathBuf]) -> Result<usize> {
+    let mut checksums = BTreeMap::new();
+    for archive in release.targets.iter().filter_map(|t| t.archive.as_ref()) {
+        let archive = Path::new(archive);
+        checksums.insert(relative(out_path, archive), archive::sha256(archive)?);
+    }
+    for dir in output_dirs.iter().filter(|d| d.is_dir()) {
+        let prefix = relative(out_path, dir);
+        for (path, sha) in archive::sha256_tree(dir)? {
+            checksums.insert(format!("{prefix}/{path}"), sha);
         }
     }
 
-    if let Some(brew) = config.brew.as_ref() {
-        let prefix = format!("{} {}", crate::console::COFFEE, style("brew").green());
-        if brew.publish {
-            let latest_v = brew::latest(brew)?;
-            if latest_v < target_v {
-                bail!("current latest version is newer, aborting publish")
+    let txt = checksums
+        .iter()
+        .map(|(path, sha)| format!("{sha}  {path}\n"))
+        .collect::<String>();
+    fs::write(out_path.join(CHECKSUMS_TXT), txt)?;
+    fs::write(
+        out_path.join(CHECKSUMS_JSON),
+        serde_json::to_string_pretty(&checksums)?,
+    )?;
+    Ok(checksums.len())
+}
+
+/// Publish stage: push what the generate stage left in `out_path`, for providers
+/// with publishing on. With `dry_run`, print what would be published instead.
+///
+/// # Errors
+///
+/// This function will return an error if publishing failed
+pub fn publish(
+    registry: &Registry,
+    config_file: &Path,
+    out_path: &Path,
+    dry_run: bool,
+) -> Result<()> {
+    let (config, providers) = load(registry, config_file)?;
+    let release = Release::load(&out_path.join(RELEASES_DIR))?;
+    let mut session = Session {
+        config: &config,
+        console: &mut EnvConsole {},
+    };
+    let version = release.version;
+    let target_v = semver::Version::parse(&version)?;
+
+    if dry_run {
+        session.console.say(&format!(
+            "{} plan for {}:",
+            crate::console::PLAN,
+            style(&version).bold()
+        ));
+    }
+    for provider in &providers {
+        let prefix = format!("{} {}", crate::console::PKG, style(provider.name()).green());
+        if !provider.publishes() {
+            if dry_run {
+                session
+                    .console
+                    .say(&format!("{prefix} publish disabled, generate only."));
+            }
+            continue;
+        }
+
+        if let Some(latest_v) = provider.latest_version()? {
+            if latest_v >= target_v {
+                session.console.say(&format!(
+                    "{prefix} published version ({latest_v}) higher/equal to target version ({target_v}), skipping publish."
+                ));
+                continue;
             }
             session.console.say(&format!(
                 "{prefix} current: {latest_v}, publishing: {target_v}..."
             ));
         }
-        brew::publish(
-            &mut session,
-            out_path,
-            &target_v.to_string(),
-            &versioned_targets,
-            brew,
-        )?;
+        if dry_run {
+            say_plan(&mut session, &provider.plan(out_path, &version)?);
+        } else {
+            provider.publish(&mut session, out_path, &version)?;
+        }
+    }
+    Ok(())
+}
+
+/// Verify stage: check downloaded archives against the checksums recorded when they
+/// were downloaded, and that generated output has everything publishing needs
+///
+/// # Errors
+///
+/// This function will return an error if anything is missing or does not match
+pub fn verify(registry: &Registry, config_file: &Path, out_path: &Path) -> Result<()> {
+    let (config, providers) = load(registry, config_file)?;
+    let release = Release::load(&out_path.join(RELEASES_DIR))?;
+    let session = Session {
+        config: &config,
+        console: &mut EnvConsole {},
+    };
+
+    for (archive, expected) in &release.checksums {
+        let actual = archive::sha256(Path::new(archive))?;
+        if &actual != expected {
+            bail!("checksum mismatch for '{archive}': expected {expected}, got {actual}");
+        }
+    }
+    session.console.say(&format!(
+        "{} {} archive(s) match their checksums",
+        crate::console::INFO,
+        release.checksums.len()
+    ));
+
+    for provider in providers.iter().filter(|p| p.publishes()) {
+        provider.verify(out_path, &release.version)?;
+        session.console.say(&format!(
+            "{} {} ready to publish {}",
+            crate::console::PKG,
+            style(provider.name()).green(),
+            release.version
+        ));
     }
     Ok(())
 }
+
+fn say_plan(session: &mut Session<'_>, actions: &[Action]) {
+    for action in actions {
+        session.console.say(&format!(
+            "   {} {} to {}",
+            style("would publish").yellow(),
+            style(&action.artifact).bold(),
+            style(&action.destination).magenta()
+        ));
+        if let Some(diff) = &action.diff {
+            for line in diff.lines() {
+                let line = match line.chars().next() {
+                    Some('+') => style(line).green(),
+                    Some('-') => style(line).red(),
+                    _ => style(line).dim(),
+                };
+                session.console.say(&format!("      {line}"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Target;
+
+    #[test]
+    fn test_write_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let releases = dir.path().join(RELEASES_DIR);
+        fs::create_dir_all(&releases).unwrap();
+        let archive = releases.join("recon-x86_64-linux.tar.gz");
+        fs::write(&archive, "archive").unwrap();
+        fs::create_dir_all(dir.path().join("recon-1.0.0/brew")).unwrap();
+        fs::write(dir.path().join("recon-1.0.0/brew/recon.rb"), "recipe").unwrap();
+        // leftovers of partial downloads and earlier versions are not part of this release
+        fs::write(releases.join(".0123abcd.part"), "partial").unwrap();
+        fs::write(releases.join("recon-0.9.0-x86_64-linux.tar.gz"), "old").unwrap();
+        fs::create_dir_all(dir.path().join("recon-0.9.0/brew")).unwrap();
+        fs::write(dir.path().join("recon-0.9.0/brew/recon.rb"), "old recipe").unwrap();
+        let release = Release {
+            version: "1.0.0".to_string(),
+            targets: vec![Target {
+                archive: Some(archive.to_string_lossy().to_string()),
+                ..Target::default()
+            }],
+            checksums: BTreeMap::new(),
+        };
+        release.save(&releases).unwrap();
+
+        let output_dirs = [dir.path().join("recon-1.0.0/brew")];
+        // rewriting does not cover the previous checksum files
+        assert_eq!(
+            write_checksums(dir.path(), &release, &output_dirs).unwrap(),
+            2
+        );
+        assert_eq!(
+            write_checksums(dir.path(), &release, &output_dirs).unwrap(),
+            2
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(CHECKSUMS_TXT)).unwrap(),
+            format!(
+                "{}  recon-1.0.0/brew/recon.rb\n{}  releases/recon-x86_64-linux.tar.gz\n",
+                archive::sha256(&dir.path().join("recon-1.0.0/brew/recon.rb")).unwrap(),
+                archive::sha256(&archive).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("rustwrap.yaml");
+        fs::write(&config_file, "targets: []\n").unwrap();
+        let releases = dir.path().join(RELEASES_DIR);
+        fs::create_dir_all(&releases).unwrap();
+        let archive = releases.join("recon-x86_64-linux.tar.gz");
+        fs::write(&archive, "archive").unwrap();
+        let archive = archive.to_string_lossy().to_string();
+        Release {
+            version: "1.0.0".to_string(),
+            targets: vec![],
+            checksums: BTreeMap::from([(
+                archive.clone(),
+                archive::sha256(Path::new(&archive)).unwrap(),
+            )]),
+        }
+        .save(&releases)
+        .unwrap();
+
+        let registry = Registry::default();
+        verify(&registry, &config_file, dir.path()).unwrap();
+        fs::write(&archive, "tampered").unwrap();
+        assert!(verify(&registry, &config_file, dir.path()).is_err());
+    }
+}
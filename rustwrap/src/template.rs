@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use minijinja::{Environment, Error, ErrorKind, UndefinedBehavior};
+use serde::Serialize;
+
+use crate::{archive, data::Target};
+
+pub const VAR_URL: &str = "__URL__";
+pub const VAR_SHA: &str = "__SHA__";
+pub const VAR_VERSION: &str = "__VERSION__";
+
+/// A target, as an item of `targets` in templates
+#[derive(Serialize, Debug, Clone)]
+pub struct TargetVars {
+    pub platform: String,
+    pub arch: String,
+    pub libc: Option<String>,
+    /// `<platform>-<arch>[-<libc>]`
+    pub slug: String,
+    pub url: String,
+    pub sha256: String,
+    /// Path of the downloaded archive
+    pub archive: String,
+}
+
+impl TargetVars {
+    /// Variables of a downloaded `target`, with its url for `version`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the target was not downloaded
+    pub fn new(target: &Target, version: &str) -> Result<Self> {
+        let archive = target
+            .archive
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("archive '{:?}' was not found", target))?;
+        Ok(Self {
+            platform: target.platform.to_string(),
+            arch: target.arch.to_string(),
+            libc: target.libc.as_ref().map(ToString::to_string),
+            slug: target.tuple_slug(),
+            url: target.url(version),
+            sha256: archive::sha256(Path::new(&archive))?,
+            archive,
+        })
+    }
+}
+
+/// Variables a template is rendered with
+#[derive(Serialize, Default, Debug)]
+pub struct Context {
+    pub name: String,
+    pub version: String,
+    /// GitHub repo (`owner/name`) from the configuration
+    pub repo: Option<String>,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub license: Option<String>,
+    pub targets: Vec<TargetVars>,
+}
+
+fn environment() -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.add_filter("sha256", |path: String| {
+        archive::sha256(Path::new(&path))
+            .map_err(|e| Error::new(ErrorKind::InvalidOperation, e.to_string()))
+    });
+    env
+}
+
+/// Make sure a template parses
+pub fn validate(template: &str) -> Result<()> {
+    let env = environment();
+    env.template_from_str(template)
+        .map(drop)
+        .context("invalid template")
+}
+
+/// Replace the variables of templates written before templates were rendered with jinja:
+/// `__VERSION__`, `__URL__[<key>]` and `__SHA__[<key>]` keyed by slug, `<platform>-<arch>`
+/// or arch, and `__URL__` and `__SHA__` of the first target when no key is used
+fn render_placeholders(template: &str, ctx: &Context) -> String {
+    let mut out = template.replace(VAR_VERSION, &ctx.version);
+    // most specific keys first, so the first target of an arch wins `[<arch>]`
+    let keys: [fn(&TargetVars) -> String; 3] = [
+        |t| t.slug.clone(),
+        |t| format!("{}-{}", t.platform, t.arch),
+        |t| t.arch.clone(),
+    ];
+    for key in keys {
+        for t in &ctx.targets {
+            out = out
+                .replace(&format!("{VAR_URL}[{}]", key(t)), &t.url)
+                .replace(&format!("{VAR_SHA}[{}]", key(t)), &t.sha256);
+        }
+    }
+    if let Some(t) = ctx.targets.first() {
+        if !out.contains(&format!("{VAR_URL}[")) {
+            out = out.replace(VAR_URL, &t.url).replace(VAR_SHA, &t.sha256);
+        }
+    }
+    out
+}
+
+/// Render a jinja template (with `trim_blocks` and `lstrip_blocks` on) with `ctx`
+///
+/// # Errors
+///
+/// This function will return an error if the template is invalid or uses a missing variable
+pub fn render(template: &str, ctx: &Context) -> Result<String> {
+    environment()
+        .render_str(&render_placeholders(template, ctx), ctx)
+        .context("cannot render template")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn target(platform: &str, arch: &str) -> TargetVars {
+        TargetVars {
+            platform: platform.to_string(),
+            arch: arch.to_string(),
+            libc: None,
+            slug: format!("{platform}-{arch}"),
+            url: format!("https://example.com/recon-{platform}-{arch}.tar.gz"),
+            sha256: format!("sha-{platform}-{arch}"),
+            archive: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let ctx = Context {
+            name: "recon".to_string(),
+            version: "1.0.1".to_string(),
+            license: Some("Apache-2.0".to_string()),
+            targets: vec![
+                target("darwin", "x64"),
+                target("linux", "x64"),
+                target("darwin", "arm64"),
+            ],
+            ..Default::default()
+        };
+        let template = r#"version "{{ version }}" {{ license | upper }}
+{% for t in targets if t.platform == "linux" %}
+  url "{{ t.url }}"
+{% endfor %}
+  url "__URL__[x64]" "__URL__[linux-x64]"
+"#;
+        assert_eq!(
+            render(template, &ctx).unwrap(),
+            r#"version "1.0.1" APACHE-2.0
+  url "https://example.com/recon-linux-x64.tar.gz"
+  url "https://example.com/recon-darwin-x64.tar.gz" "https://example.com/recon-linux-x64.tar.gz"
+"#
+        );
+        assert!(render("{{ homepage.url }}", &ctx).is_err());
+
+        // an explicit `libc: gnu` still answers to `<platform>-<arch>`
+        let gnu = TargetVars {
+            libc: Some("gnu".to_string()),
+            slug: "linux-x64-gnu".to_string(),
+            ..target("linux", "x64")
+        };
+        let ctx = Context {
+            targets: vec![gnu],
+            ..Default::default()
+        };
+        assert_eq!(
+            render("__URL__[linux-x64] __SHA__[linux-x64-gnu]", &ctx).unwrap(),
+            "https://example.com/recon-linux-x64.tar.gz sha-linux-x64"
+        );
+        assert!(validate("{% if %}").is_err());
+    }
+}